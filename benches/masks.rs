@@ -0,0 +1,223 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use speedy_mask::{
+    compute_mask_size, generate_mask, generate_mask_into, generate_masks_from_bufreader, MaskConfig,
+};
+
+const CORPUS_WORDS: usize = 100_000;
+
+/// System allocator counting allocations, to check the buffer reuse beyond the timings.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations_of<T>(run: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(run());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Reproducible wordlist mixing lengths and classes, a handful of shapes dominating like in
+/// leaked password lists.
+fn synthetic_corpus() -> Vec<String> {
+    const CHARSETS: [&[u8]; 4] = [
+        b"abcdefghijklmnopqrstuvwxyz",
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+        b"0123456789",
+        b"!@#$%&*?",
+    ];
+    // weights of lower, upper, digit and special characters
+    const WEIGHTS: [u64; 4] = [70, 10, 15, 5];
+    let mut state: u64 = 0x853c49e6748fea9b;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        state >> 33
+    };
+
+    (0..CORPUS_WORDS)
+        .map(|_| {
+            let length = 6 + (next() % 7) as usize;
+            (0..length)
+                .map(|_| {
+                    let mut draw = next() % WEIGHTS.iter().sum::<u64>();
+                    let class = WEIGHTS
+                        .iter()
+                        .position(|&weight| {
+                            let is_drawn = draw < weight;
+                            draw = draw.saturating_sub(weight);
+                            is_drawn
+                        })
+                        .unwrap();
+                    let charset = CHARSETS[class];
+                    charset[next() as usize % charset.len()] as char
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn bench_generate_mask(c: &mut Criterion) {
+    let config = MaskConfig::default();
+
+    c.bench_function("generate_mask", |b| {
+        b.iter(|| generate_mask(black_box("P@ssw0rd2023!"), &config))
+    });
+}
+
+fn bench_compute_mask_size(c: &mut Criterion) {
+    let config = MaskConfig::default();
+
+    c.bench_function("compute_mask_size", |b| {
+        b.iter(|| {
+            compute_mask_size(black_box("ullllldddds"), usize::MAX, config.charset_sizes()).unwrap()
+        })
+    });
+}
+
+fn bench_wordlist_counting(c: &mut Criterion) {
+    let config = MaskConfig::default();
+    let corpus = synthetic_corpus();
+    let wordlist = corpus.join("\n");
+    let mut group = c.benchmark_group("count_100k_words");
+
+    group.bench_function("generate_masks_from_bufreader", |b| {
+        b.iter(|| generate_masks_from_bufreader(&mut Cursor::new(wordlist.as_bytes()), &config))
+    });
+
+    group.bench_function("hashmap", |b| {
+        b.iter(|| {
+            let mut masks_counts: HashMap<String, usize> = HashMap::new();
+            for word in &corpus {
+                let mask = generate_mask(word, &config).unwrap();
+                *masks_counts.entry(mask).or_insert(0) += 1;
+            }
+            masks_counts
+        })
+    });
+
+    group.bench_function("sorted_vec", |b| {
+        b.iter(|| {
+            let mut masks: Vec<String> = corpus
+                .iter()
+                .map(|word| generate_mask(word, &config).unwrap())
+                .collect();
+            masks.sort_unstable();
+
+            let mut masks_counts: Vec<(String, usize)> = Vec::new();
+            for mask in masks {
+                match masks_counts.last_mut() {
+                    Some((last_mask, count)) if *last_mask == mask => *count += 1,
+                    _ => masks_counts.push((mask, 1)),
+                }
+            }
+            masks_counts
+        })
+    });
+
+    group.finish();
+}
+
+/// Few distinct masks over many words, where reusing the mask buffer saves an allocation per word.
+fn bench_mask_reuse(c: &mut Criterion) {
+    let config = MaskConfig::default();
+    let corpus: Vec<String> = (0..CORPUS_WORDS)
+        .map(|index| format!("Password{}", index % 100))
+        .collect();
+    let wordlist = corpus.join("\n");
+    let mut mask = String::with_capacity(64);
+
+    let allocating = allocations_of(|| {
+        for word in &corpus {
+            black_box(generate_mask(word, &config).unwrap());
+        }
+    });
+    let reusing = allocations_of(|| {
+        for word in &corpus {
+            generate_mask_into(word, &config, &mut mask).unwrap();
+        }
+    });
+    let counting = allocations_of(|| {
+        generate_masks_from_bufreader(&mut Cursor::new(wordlist.as_bytes()), &config).unwrap()
+    });
+    assert!(allocating >= CORPUS_WORDS, "{} allocations", allocating);
+    // a buffer already grown to the longest mask is never reallocated
+    assert_eq!(reusing, 0, "{} allocations", reusing);
+    // one allocation per line read, masks being copied only when new
+    assert!(
+        counting < CORPUS_WORDS + CORPUS_WORDS / 100,
+        "{} allocations",
+        counting
+    );
+
+    let mut group = c.benchmark_group("reused_mask_buffer");
+    group.bench_function("generate_mask", |b| {
+        b.iter(|| {
+            for word in &corpus {
+                black_box(generate_mask(black_box(word), &config).unwrap());
+            }
+        })
+    });
+    group.bench_function("generate_mask_into", |b| {
+        b.iter(|| {
+            for word in &corpus {
+                generate_mask_into(black_box(word), &config, &mut mask).unwrap();
+            }
+        })
+    });
+    group.bench_function("generate_masks_from_bufreader", |b| {
+        b.iter(|| generate_masks_from_bufreader(&mut Cursor::new(wordlist.as_bytes()), &config))
+    });
+    group.finish();
+}
+
+/// Words mixing ASCII and non-ASCII letters, the latter classified without the ASCII class table.
+fn bench_mixed_wordlist(c: &mut Criterion) {
+    const STEMS: [&str; 4] = [
+        "P@ssw0rd",
+        "Passw\u{f6}rt",
+        "contrase\u{f1}a",
+        "\u{43f}\u{430}\u{440}\u{43e}\u{43b}\u{44c}",
+    ];
+    let config = MaskConfig::builder().unicode(true).build();
+    let corpus: Vec<String> = (0..CORPUS_WORDS)
+        .map(|index| format!("{}{}", STEMS[index % STEMS.len()], index % 1000))
+        .collect();
+
+    c.bench_function("generate_mask_mixed_wordlist", |b| {
+        b.iter(|| {
+            for word in &corpus {
+                black_box(generate_mask(black_box(word), &config).unwrap());
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_generate_mask,
+    bench_compute_mask_size,
+    bench_wordlist_counting,
+    bench_mask_reuse,
+    bench_mixed_wordlist
+);
+criterion_main!(benches);
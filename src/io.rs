@@ -0,0 +1,1471 @@
+//! Reading of wordlists and mask lists into mask counts.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "parallel")]
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::mask::{
+    generate_mask, generate_mask_into, normalize_mask, ComputedMask, MaskConfig, MaskError,
+    OnUnknown,
+};
+#[cfg(feature = "parallel")]
+use crate::select::sort_masks;
+use crate::select::ParseOptions;
+use crate::stats::combined_coverage;
+
+/// Wordlist path standing for the standard input.
+pub const STDIN_PATH: &str = "-";
+
+const BYTE_ORDER_MARK: char = '\u{feff}';
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_LINES: usize = 1 << 16;
+/// Line numbers of skipped words kept in a [`SkipReport`].
+pub const MAX_SKIPPED_LINES: usize = 20;
+
+/// Failure to open or read a wordlist, telling which one when several are processed.
+#[derive(Debug)]
+pub struct FileError {
+    pub kind: FileErrorKind,
+    /// Wordlist that failed, `None` for errors converted from a bare [`io::Error`].
+    pub path: Option<PathBuf>,
+    pub source: io::Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileErrorKind {
+    OpenFailed,
+    ReadFailed,
+}
+
+impl FileError {
+    fn new(kind: FileErrorKind, path: &Path, source: io::Error) -> Self {
+        Self {
+            kind,
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let action = match self.kind {
+            FileErrorKind::OpenFailed => "open",
+            FileErrorKind::ReadFailed => "read",
+        };
+        match &self.path {
+            Some(path) => write!(f, "cannot {} {}: {}", action, path.display(), self.source),
+            None => write!(f, "cannot {}: {}", action, self.source),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(source: io::Error) -> Self {
+        Self {
+            kind: FileErrorKind::ReadFailed,
+            path: None,
+            source,
+        }
+    }
+}
+
+impl From<FileError> for io::Error {
+    fn from(error: FileError) -> Self {
+        io::Error::new(error.source.kind(), error)
+    }
+}
+
+/// Read one mask per line, native or hashcat, into their form normalized under `config`.
+///
+/// Blank lines are ignored, and an invalid mask is reported as invalid data.
+pub fn read_mask_list<R>(line_reader: R, config: &MaskConfig) -> io::Result<HashSet<String>>
+where
+    R: BufRead,
+{
+    let mut masks = HashSet::new();
+
+    for (index, line) in lines(line_reader).enumerate() {
+        let line = line?;
+        let mask = line.trim();
+        if mask.is_empty() {
+            continue;
+        }
+
+        let mask = normalize_mask(mask, config).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, error),
+            )
+        })?;
+        masks.insert(mask);
+    }
+
+    Ok(masks)
+}
+
+/// Words dropped while counting masks because they could not be classified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkipReport {
+    pub words: usize,
+    /// Number of dropped words per offending character, only the first one of a word is counted.
+    pub invalid_characters: BTreeMap<char, usize>,
+    /// Lines of a weighted wordlist without a valid count, see [`ReadOptions::weight_delimiter`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub malformed_lines: usize,
+    /// Lines longer than [`ReadOptions::max_line_length`], dropped without being buffered.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub long_lines: usize,
+    /// First [`MAX_SKIPPED_LINES`] lines dropped for an invalid character, in reading order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lines: Vec<SkippedLine>,
+    /// Files of a walked directory left out for not being UTF-8 text, see
+    /// [`ReadOptions::walk_depth`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub binary_files: usize,
+}
+
+/// Line of a wordlist dropped for an invalid character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkippedLine {
+    /// Wordlist of the line, `None` when counted from a bare reader.
+    pub path: Option<PathBuf>,
+    /// 1-based number of the line within its wordlist.
+    pub line: usize,
+}
+
+impl SkipReport {
+    /// Add the counts of `other`, its skipped lines coming after the ones of `self`.
+    pub fn merge(&mut self, other: SkipReport) {
+        self.words = self.words.saturating_add(other.words);
+        self.malformed_lines = self.malformed_lines.saturating_add(other.malformed_lines);
+        self.long_lines = self.long_lines.saturating_add(other.long_lines);
+        self.binary_files = self.binary_files.saturating_add(other.binary_files);
+        self.lines.extend(other.lines);
+        self.lines.truncate(MAX_SKIPPED_LINES);
+        for (char, count) in other.invalid_characters {
+            let total_count = self.invalid_characters.entry(char).or_insert(0);
+            *total_count = total_count.saturating_add(count);
+        }
+    }
+
+    fn record(&mut self, invalid_character: char, weight: usize, line_number: usize) {
+        self.words = self.words.saturating_add(weight);
+        if self.lines.len() < MAX_SKIPPED_LINES {
+            self.lines.push(SkippedLine {
+                path: None,
+                line: line_number,
+            });
+        }
+        let count = self
+            .invalid_characters
+            .entry(invalid_character)
+            .or_insert(0);
+        *count = count.saturating_add(weight);
+    }
+}
+
+/// Mask occurrences gathered from a wordlist.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaskCounts {
+    pub masks: HashMap<String, usize>,
+    /// Non-empty lines read, including words that could not be classified.
+    pub total_words: usize,
+    pub skipped: SkipReport,
+    /// Words whose mask was left uncounted because [`ReadOptions::max_distinct_masks`] was
+    /// reached.
+    pub capped_words: usize,
+}
+
+impl MaskCounts {
+    /// Add the occurrences and words of `other` to these counts, saturating at `usize::MAX`.
+    pub fn merge(&mut self, other: MaskCounts) {
+        self.merge_capped(other, usize::MAX);
+    }
+
+    /// Multiply every count by `factor`, extrapolating sampled counts, saturating at
+    /// `usize::MAX`.
+    ///
+    /// Mask, word and skipped line counts are scaled, the skipped line numbers and binary files
+    /// are left as they are.
+    fn scale(&mut self, factor: usize) {
+        if factor == 1 {
+            return;
+        }
+
+        let scale = |count: &mut usize| *count = count.saturating_mul(factor);
+        self.masks.values_mut().for_each(scale);
+        scale(&mut self.total_words);
+        scale(&mut self.capped_words);
+        scale(&mut self.skipped.words);
+        scale(&mut self.skipped.malformed_lines);
+        scale(&mut self.skipped.long_lines);
+        self.skipped.invalid_characters.values_mut().for_each(scale);
+    }
+
+    /// Same as [`MaskCounts::merge`], masks of `other` beyond `max_distinct_masks` being moved
+    /// to the capped words.
+    ///
+    /// When the cap is reached, the new masks kept are the most frequent of `other`, ties going
+    /// to the lexically smallest, rather than following the hash order.
+    fn merge_capped(&mut self, other: MaskCounts, max_distinct_masks: usize) {
+        let mut masks: Vec<(String, usize)> = other.masks.into_iter().collect();
+        if self.masks.len().saturating_add(masks.len()) > max_distinct_masks {
+            masks.sort_unstable_by(|(mask_0, count_0), (mask_1, count_1)| {
+                count_1.cmp(count_0).then_with(|| mask_0.cmp(mask_1))
+            });
+        }
+
+        for (mask, count) in masks {
+            let is_full = self.masks.len() >= max_distinct_masks;
+            match self.masks.get_mut(&mask) {
+                Some(total_count) => *total_count = total_count.saturating_add(count),
+                None if !is_full => {
+                    self.masks.insert(mask, count);
+                }
+                None => self.capped_words = self.capped_words.saturating_add(count),
+            }
+        }
+        self.total_words = self.total_words.saturating_add(other.total_words);
+        self.skipped.merge(other.skipped);
+        self.capped_words = self.capped_words.saturating_add(other.capped_words);
+    }
+
+    /// Count a classified word, blank lines are ignored.
+    ///
+    /// The mask is only copied the first time it is seen, and only while fewer than
+    /// `max_distinct_masks` masks are known.
+    fn record_mask(
+        &mut self,
+        mask: Result<&str, MaskError>,
+        word: &WeightedWord,
+        max_distinct_masks: usize,
+    ) {
+        let weight = word.weight;
+        match mask {
+            Ok(mask) => {
+                self.total_words = self.total_words.saturating_add(weight);
+                let is_full = self.masks.len() >= max_distinct_masks;
+                match self.masks.get_mut(mask) {
+                    Some(count) => *count = count.saturating_add(weight),
+                    None if !is_full => {
+                        self.masks.insert(mask.to_string(), weight);
+                    }
+                    None => self.capped_words = self.capped_words.saturating_add(weight),
+                }
+            }
+            Err(MaskError::InvalidCharacter(char)) => {
+                self.total_words = self.total_words.saturating_add(weight);
+                self.skipped.record(char, weight, word.line_number);
+            }
+            // only blank lines fail otherwise, they are not words
+            Err(_) => {}
+        }
+    }
+
+    /// Fraction of the wordlist matched by `selected`, `0.0` for an empty wordlist.
+    pub fn coverage(&self, selected: &[ComputedMask]) -> f64 {
+        combined_coverage(selected, self.total_words)
+    }
+}
+
+/// How lines are turned into words before classification.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Count repeated words only once, remembering every distinct word read.
+    pub dedup: bool,
+    /// Draw a progress bar on stderr while reading wordlist files, needs the `indicatif` feature.
+    pub progress: bool,
+    /// Stop counting new masks once this many are known, bounding memory on untrusted input.
+    ///
+    /// Known masks keep being counted, words of any other mask are counted as capped. With the
+    /// `parallel` feature, lines are counted by chunks and the masks new to a chunk reaching the
+    /// cap are kept by decreasing count then mask, so the kept masks are reproducible but may
+    /// differ from the first ones seen by a sequential reading.
+    pub max_distinct_masks: Option<usize>,
+    /// Read only one line in this many, starting with the first, and scale the counts up
+    /// accordingly. Counts, coverages and skip reports are then estimates.
+    pub sample_every: Option<usize>,
+    /// Read lines as `COUNT<delimiter>WORD`, e.g. `uniq -c` output, the word standing for
+    /// `COUNT` occurrences. Lines without a valid count are reported as malformed.
+    pub weight_delimiter: Option<char>,
+    /// Skip lines longer than this many bytes, reading them in chunks instead of buffering them
+    /// whole, so a corrupt file without line breaks cannot exhaust memory.
+    pub max_line_length: Option<usize>,
+    /// Read the files of directory paths down to this depth, 1 reading only the files directly
+    /// inside; `None` fails on directories.
+    ///
+    /// Files of a walked directory that are not UTF-8 text, e.g. archives, are skipped and
+    /// counted in [`SkipReport::binary_files`] without their partial counts. Any other failure,
+    /// such as an unreadable file or subdirectory, aborts like for a file given directly.
+    #[cfg(feature = "walkdir")]
+    pub walk_depth: Option<usize>,
+}
+
+impl ReadOptions {
+    fn max_distinct_masks(&self) -> usize {
+        self.max_distinct_masks.unwrap_or(usize::MAX)
+    }
+
+    pub(crate) fn sample_every(&self) -> usize {
+        self.sample_every.unwrap_or(1).max(1)
+    }
+}
+
+pub fn generate_masks_from_bufreader<R>(
+    line_reader: &mut R,
+    config: &MaskConfig,
+) -> io::Result<MaskCounts>
+where
+    R: BufRead,
+{
+    generate_masks_with_options(line_reader, config, &ReadOptions::default())
+}
+
+/// Same as [`generate_masks_from_bufreader`], reading words as described by `read_options`.
+pub fn generate_masks_with_options<R>(
+    line_reader: &mut R,
+    config: &MaskConfig,
+    read_options: &ReadOptions,
+) -> io::Result<MaskCounts>
+where
+    R: BufRead,
+{
+    generate_masks_with_visitor(line_reader, config, read_options, &mut |_, _| {})
+}
+
+/// Same as [`generate_masks_with_options`], calling `visitor` with each classified word and its
+/// mask, so custom statistics can be gathered in the same pass.
+///
+/// Words that cannot be classified are not visited, and a weighted word is visited once.
+pub fn generate_masks_with_visitor<R, V>(
+    line_reader: &mut R,
+    config: &MaskConfig,
+    read_options: &ReadOptions,
+    visitor: &mut V,
+) -> io::Result<MaskCounts>
+where
+    R: BufRead,
+    V: FnMut(&str, &str),
+{
+    let mut masks_counts = MaskCounts::default();
+    let mut mask = String::new();
+    let max_distinct_masks = read_options.max_distinct_masks();
+
+    for line in read_words(line_reader, read_options) {
+        line?.record(
+            &mut masks_counts,
+            config,
+            &mut mask,
+            max_distinct_masks,
+            visitor,
+        );
+    }
+
+    masks_counts.scale(read_options.sample_every());
+    Ok(masks_counts)
+}
+
+/// Classify `word` and count its mask in `masks`, for custom ingestion loops.
+///
+/// Empty words are skipped without error, like blank lines of a wordlist.
+pub fn record_word(
+    masks: &mut HashMap<String, usize>,
+    word: &str,
+    config: &MaskConfig,
+) -> Result<(), MaskError> {
+    match generate_mask(word, config) {
+        Ok(mask) => {
+            let count = masks.entry(mask).or_insert(0);
+            *count = count.saturating_add(1);
+            Ok(())
+        }
+        Err(MaskError::EmptyWord) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Lines of a wordlist, without the trailing `\r` of CRLF line endings nor the byte order mark
+/// some Windows tools write before the first line.
+pub(crate) fn lines<R>(line_reader: R) -> impl Iterator<Item = io::Result<String>>
+where
+    R: BufRead,
+{
+    BoundedLines::new(line_reader, None).map(|line| line.map(Option::unwrap_or_default))
+}
+
+/// Same as [`lines`], lines longer than `max_line_length` bytes being yielded as `None`.
+pub(crate) struct BoundedLines<R> {
+    line_reader: R,
+    max_line_length: Option<usize>,
+    is_first_line: bool,
+}
+
+impl<R: BufRead> BoundedLines<R> {
+    pub(crate) fn new(line_reader: R, max_line_length: Option<usize>) -> Self {
+        Self {
+            line_reader,
+            max_line_length,
+            is_first_line: true,
+        }
+    }
+
+    /// Read up to the next line break, dropping the bytes past `max_line_length` plus a `\r`.
+    ///
+    /// Returns `None` at the end of the input, and whether the line was too long otherwise.
+    fn read_bounded_line(
+        &mut self,
+        line: &mut Vec<u8>,
+        max_line_length: usize,
+    ) -> io::Result<Option<bool>> {
+        let mut is_empty = true;
+        let mut is_too_long = false;
+
+        loop {
+            let buffer = match self.line_reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            if buffer.is_empty() {
+                return Ok((!is_empty).then_some(is_too_long));
+            }
+            is_empty = false;
+
+            let (chunk, has_line_break) = match buffer.iter().position(|&byte| byte == b'\n') {
+                Some(end) => (&buffer[..end], true),
+                None => (buffer, false),
+            };
+            if !is_too_long && line.len() + chunk.len() > max_line_length.saturating_add(1) {
+                is_too_long = true;
+                line.clear();
+            }
+            if !is_too_long {
+                line.extend_from_slice(chunk);
+            }
+
+            let consumed = chunk.len() + usize::from(has_line_break);
+            self.line_reader.consume(consumed);
+            if has_line_break {
+                return Ok(Some(is_too_long));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for BoundedLines<R> {
+    type Item = io::Result<Option<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = match self.max_line_length {
+            None => {
+                let mut line = String::new();
+                match self.line_reader.read_line(&mut line) {
+                    Ok(0) => return None,
+                    Ok(_) => {}
+                    Err(error) => return Some(Err(error)),
+                }
+                if line.ends_with('\n') {
+                    line.pop();
+                }
+                line
+            }
+            Some(max_line_length) => {
+                let mut line = Vec::new();
+                match self.read_bounded_line(&mut line, max_line_length) {
+                    Ok(None) => return None,
+                    Ok(Some(true)) => {
+                        self.is_first_line = false;
+                        return Some(Ok(None));
+                    }
+                    Ok(Some(false)) => {}
+                    Err(error) => return Some(Err(error)),
+                }
+                match String::from_utf8(line) {
+                    Ok(line) => line,
+                    Err(_) => {
+                        return Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "stream did not contain valid UTF-8",
+                        )))
+                    }
+                }
+            }
+        };
+
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        if self.is_first_line && line.starts_with(BYTE_ORDER_MARK) {
+            line.drain(..BYTE_ORDER_MARK.len_utf8());
+        }
+        self.is_first_line = false;
+
+        match self.max_line_length {
+            Some(max_line_length) if line.len() > max_line_length => Some(Ok(None)),
+            _ => Some(Ok(Some(line))),
+        }
+    }
+}
+
+/// Word of a wordlist line and the number of occurrences it stands for.
+struct WeightedWord {
+    word: String,
+    weight: usize,
+    /// 1-based number of the line in its wordlist.
+    line_number: usize,
+}
+
+impl WeightedWord {
+    /// Split a `COUNT<delimiter>WORD` line, leading spaces before the count being ignored.
+    fn parse(line: &str, delimiter: char, line_number: usize) -> Option<Self> {
+        let (weight, word) = line.trim_start().split_once(delimiter)?;
+        Some(Self {
+            word: word.to_string(),
+            weight: weight.parse().ok()?,
+            line_number,
+        })
+    }
+}
+
+/// Line read by [`read_words`].
+enum ReadLine {
+    Word(WeightedWord),
+    /// Weighted line without a valid count.
+    Malformed,
+    /// Line longer than [`ReadOptions::max_line_length`].
+    TooLong,
+}
+
+impl ReadLine {
+    /// Count `self` into `masks_counts`, classifying its word with the `mask` buffer and passing
+    /// it to `visitor` when it is valid.
+    fn record(
+        &self,
+        masks_counts: &mut MaskCounts,
+        config: &MaskConfig,
+        mask: &mut String,
+        max_distinct_masks: usize,
+        visitor: &mut impl FnMut(&str, &str),
+    ) {
+        match self {
+            ReadLine::Word(word) => {
+                let result = generate_mask_into(&word.word, config, mask);
+                match (&result, config.on_unknown()) {
+                    (Ok(()), _) => visitor(&word.word, mask),
+                    (Err(MaskError::InvalidCharacter(_)), OnUnknown::Skip) => return,
+                    (Err(_), _) => {}
+                }
+                masks_counts.record_mask(result.map(|()| mask.as_str()), word, max_distinct_masks);
+            }
+            ReadLine::Malformed => masks_counts.skipped.malformed_lines += 1,
+            ReadLine::TooLong => masks_counts.skipped.long_lines += 1,
+        }
+    }
+}
+
+/// Words of the lines, along with the lines skipped as malformed or too long.
+fn read_words<'a, R>(
+    line_reader: R,
+    read_options: &'a ReadOptions,
+) -> impl Iterator<Item = io::Result<ReadLine>> + 'a
+where
+    R: BufRead + 'a,
+{
+    let mut seen_words = HashSet::new();
+    let sample_every = read_options.sample_every();
+
+    BoundedLines::new(line_reader, read_options.max_line_length)
+        .enumerate()
+        .step_by(sample_every)
+        .map(move |(index, line)| {
+            line.map(|line| match (line, read_options.weight_delimiter) {
+                (None, _) => ReadLine::TooLong,
+                (Some(line), Some(delimiter)) => {
+                    match WeightedWord::parse(&line, delimiter, index + 1) {
+                        Some(word) => ReadLine::Word(word),
+                        None => ReadLine::Malformed,
+                    }
+                }
+                (Some(line), None) => ReadLine::Word(WeightedWord {
+                    word: line,
+                    weight: 1,
+                    line_number: index + 1,
+                }),
+            })
+        })
+        .filter(move |word| match word {
+            Ok(ReadLine::Word(word)) if read_options.dedup => seen_words.insert(word.word.clone()),
+            _ => true,
+        })
+}
+
+/// Words of the lines read as described by `read_options` along with their weight, malformed and
+/// too long lines left out, for the passes re-reading a counted wordlist.
+pub(crate) fn weighted_words<'a, R>(
+    line_reader: R,
+    read_options: &'a ReadOptions,
+) -> impl Iterator<Item = io::Result<(String, usize)>> + 'a
+where
+    R: BufRead + 'a,
+{
+    read_words(line_reader, read_options).filter_map(|line| match line {
+        Ok(ReadLine::Word(word)) => Some(Ok((word.word, word.weight))),
+        Ok(ReadLine::Malformed | ReadLine::TooLong) => None,
+        Err(error) => Some(Err(error)),
+    })
+}
+
+/// Lazily yield the mask of each line, blank lines giving [`MaskError::EmptyWord`].
+///
+/// Unlike [`generate_masks_from_bufreader`], words that cannot be classified are
+/// yielded as errors instead of being skipped.
+pub fn masks<'a, R>(
+    line_reader: R,
+    config: &'a MaskConfig,
+) -> impl Iterator<Item = io::Result<Result<String, MaskError>>> + 'a
+where
+    R: BufRead + 'a,
+{
+    lines(line_reader).map(move |word| word.map(|word| generate_mask(&word, config)))
+}
+
+/// Same as [`generate_masks_with_options`], classifying chunks of lines on the rayon thread pool.
+#[cfg(feature = "parallel")]
+pub fn generate_masks_parallel<R>(
+    line_reader: &mut R,
+    config: &MaskConfig,
+    read_options: &ReadOptions,
+) -> io::Result<MaskCounts>
+where
+    R: BufRead,
+{
+    use rayon::prelude::*;
+
+    let mut masks_counts = MaskCounts::default();
+    let max_distinct_masks = read_options.max_distinct_masks();
+    let mut words = read_words(line_reader, read_options);
+
+    loop {
+        let chunk = words
+            .by_ref()
+            .take(PARALLEL_CHUNK_LINES)
+            .collect::<io::Result<Vec<ReadLine>>>()?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        let chunk_counts = chunk
+            .par_iter()
+            .fold(
+                || (MaskCounts::default(), String::new()),
+                |(mut chunk_counts, mut mask), line| {
+                    line.record(
+                        &mut chunk_counts,
+                        config,
+                        &mut mask,
+                        usize::MAX,
+                        &mut |_, _| {},
+                    );
+                    (chunk_counts, mask)
+                },
+            )
+            .map(|(chunk_counts, _)| chunk_counts)
+            .reduce(MaskCounts::default, |mut left, right| {
+                left.merge(right);
+                left
+            });
+        // chunks are bounded by their line count, the cap only applies to the overall counts
+        masks_counts.merge_capped(chunk_counts, max_distinct_masks);
+    }
+
+    masks_counts.scale(read_options.sample_every());
+    Ok(masks_counts)
+}
+
+/// Mask occurrences shared between threads, each one feeding words through [`Self::add_word`].
+///
+/// Clones share the same counts, masks being classified before the lock is taken.
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Default)]
+pub struct SharedMaskCounts {
+    masks: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+#[cfg(feature = "parallel")]
+impl SharedMaskCounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the mask of `word`, empty words being ignored like in [`record_word`].
+    pub fn add_word(&self, word: &str, config: &MaskConfig) -> Result<(), MaskError> {
+        let mask = match generate_mask(word, config) {
+            Ok(mask) => mask,
+            Err(MaskError::EmptyWord) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        *self.lock().entry(mask).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Copy of the masks counted so far.
+    pub fn masks(&self) -> HashMap<String, usize> {
+        self.lock().clone()
+    }
+
+    /// Same as [`sort_masks`] over the masks counted so far.
+    pub fn sort_masks(&self, maximum_size: usize, config: &MaskConfig) -> Vec<ComputedMask> {
+        sort_masks(&self.lock(), maximum_size, config)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, usize>> {
+        // a thread panicking between two increments leaves the counts consistent
+        self.masks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Open a wordlist for line by line reading, [`STDIN_PATH`] reading the standard input.
+///
+/// With the `gzip` feature, inputs starting with the gzip magic bytes are decompressed on the
+/// fly, and a `.gz` file that is not actually compressed is reported as invalid data.
+pub fn open_wordlist<P>(path: P) -> io::Result<Box<dyn BufRead>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file_reader: Box<dyn BufRead> = if path == Path::new(STDIN_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    decode_wordlist(path, file_reader)
+}
+
+/// Same as [`open_wordlist`], with a progress bar on stderr following the bytes read from the
+/// file and cleared once the reader is dropped.
+///
+/// The standard input and files without a known size are read without a bar.
+#[cfg(feature = "indicatif")]
+pub fn open_wordlist_with_progress<P>(path: P) -> io::Result<Box<dyn BufRead>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if path == Path::new(STDIN_PATH) {
+        return open_wordlist(path);
+    }
+
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return decode_wordlist(path, Box::new(BufReader::new(file)));
+    }
+
+    let style = indicatif::ProgressStyle::with_template(
+        "{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+    )
+    .expect("valid progress template");
+    let progress_bar = indicatif::ProgressBar::new(metadata.len())
+        .with_style(style)
+        .with_finish(indicatif::ProgressFinish::AndClear);
+
+    decode_wordlist(path, Box::new(BufReader::new(progress_bar.wrap_read(file))))
+}
+
+/// Decompress gzip wordlists with the `gzip` feature, passing other readers through.
+#[cfg_attr(not(feature = "gzip"), allow(unused_mut, unused_variables))]
+fn decode_wordlist(path: &Path, mut file_reader: Box<dyn BufRead>) -> io::Result<Box<dyn BufRead>> {
+    #[cfg(feature = "gzip")]
+    {
+        let is_gzip = file_reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+        let has_gz_extension = path.extension().is_some_and(|extension| extension == "gz");
+
+        if is_gzip {
+            let decoder = flate2::bufread::MultiGzDecoder::new(file_reader);
+            return Ok(Box::new(BufReader::new(decoder)));
+        }
+
+        if has_gz_extension {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has a .gz extension but is not gzip data",
+                    path.display()
+                ),
+            ));
+        }
+    }
+
+    Ok(file_reader)
+}
+
+/// Mask counts of every wordlist summed, as read by [`parse_files`] before selection.
+///
+/// [`parse_files`]: crate::parse_files
+pub fn count_files<P>(paths: &[P], options: &ParseOptions) -> Result<MaskCounts, FileError>
+where
+    P: AsRef<Path>,
+{
+    let mut mask_counts = MaskCounts::default();
+    for path in paths {
+        let path = path.as_ref();
+        #[cfg(feature = "walkdir")]
+        if let (Some(walk_depth), true) = (options.read.walk_depth, path.is_dir()) {
+            for file_path in walk_wordlists(path, walk_depth)? {
+                match count_file(&file_path, options) {
+                    Ok(file_counts) => mask_counts.merge(file_counts),
+                    Err(error)
+                        if error.kind == FileErrorKind::ReadFailed
+                            && error.source.kind() == io::ErrorKind::InvalidData =>
+                    {
+                        mask_counts.skipped.binary_files += 1;
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            continue;
+        }
+        mask_counts.merge(count_file(path, options)?);
+    }
+
+    Ok(mask_counts)
+}
+
+/// Files under `directory` down to `max_depth`, sorted by path within each directory.
+#[cfg(feature = "walkdir")]
+pub fn walk_wordlists(directory: &Path, max_depth: usize) -> Result<Vec<PathBuf>, FileError> {
+    let mut file_paths = Vec::new();
+
+    for entry in walkdir::WalkDir::new(directory)
+        .max_depth(max_depth)
+        .sort_by_file_name()
+    {
+        let entry = entry.map_err(|error| {
+            let path = error.path().unwrap_or(directory).to_path_buf();
+            FileError::new(FileErrorKind::OpenFailed, &path, error.into())
+        })?;
+        if entry.file_type().is_file() {
+            file_paths.push(entry.into_path());
+        }
+    }
+
+    Ok(file_paths)
+}
+
+fn count_file(path: &Path, options: &ParseOptions) -> Result<MaskCounts, FileError> {
+    #[cfg(feature = "indicatif")]
+    let opened = if options.read.progress {
+        open_wordlist_with_progress(path)
+    } else {
+        open_wordlist(path)
+    };
+    #[cfg(not(feature = "indicatif"))]
+    let opened = open_wordlist(path);
+    let mut line_reader =
+        opened.map_err(|error| FileError::new(FileErrorKind::OpenFailed, path, error))?;
+
+    let mut mask_counts = read_mask_counts(&mut line_reader, options)
+        .map_err(|error| FileError::new(FileErrorKind::ReadFailed, path, error))?;
+    for skipped_line in &mut mask_counts.skipped.lines {
+        skipped_line.path = Some(path.to_path_buf());
+    }
+
+    Ok(mask_counts)
+}
+
+/// Rough number of bytes held by a mask count map: its table slots, one control byte each, plus
+/// the heap buffers of the keys.
+pub fn estimated_memory(masks: &HashMap<String, usize>) -> usize {
+    let slots = masks.capacity() * (std::mem::size_of::<(String, usize)>() + 1);
+    let keys: usize = masks.keys().map(String::capacity).sum();
+    std::mem::size_of::<HashMap<String, usize>>() + slots + keys
+}
+
+/// Write `mask_counts` as JSON so a later run can [`load_counts`] and keep adding words.
+#[cfg(feature = "serde")]
+pub fn save_counts<P>(mask_counts: &MaskCounts, path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut writer = io::BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, mask_counts)?;
+    io::Write::flush(&mut writer)
+}
+
+/// Read counts written by [`save_counts`], to be merged with [`MaskCounts::merge`].
+#[cfg(feature = "serde")]
+pub fn load_counts<P>(path: P) -> io::Result<MaskCounts>
+where
+    P: AsRef<Path>,
+{
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Add the occurrences of `other` to `masks`, saturating at `usize::MAX`.
+pub fn merge_counts(masks: &mut HashMap<String, usize>, other: HashMap<String, usize>) {
+    for (mask, count) in other {
+        let total_count = masks.entry(mask).or_insert(0);
+        *total_count = total_count.saturating_add(count);
+    }
+}
+
+pub(crate) fn read_mask_counts<R>(
+    line_reader: &mut R,
+    options: &ParseOptions,
+) -> io::Result<MaskCounts>
+where
+    R: BufRead,
+{
+    #[cfg(not(feature = "parallel"))]
+    return generate_masks_with_options(line_reader, &options.config, &options.read);
+    #[cfg(feature = "parallel")]
+    return generate_masks_parallel(line_reader, &options.config, &options.read);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::io::{self, Cursor};
+    use std::path::Path;
+
+    use super::*;
+    use crate::{parse_reader, sort_masks, MaskConfig, MaskError, ParseOptions};
+
+    #[test]
+    fn file_errors_name_their_path() {
+        let error = count_files(&["missing.txt"], &ParseOptions::default()).unwrap_err();
+
+        assert_eq!(error.kind, FileErrorKind::OpenFailed);
+        assert_eq!(error.path.as_deref(), Some(Path::new("missing.txt")));
+        assert!(error.to_string().starts_with("cannot open missing.txt: "));
+        let io_error: io::Error = error.into();
+        assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn visitor_sees_classified_words() {
+        let mut wordlist = Cursor::new(b"abc\nHello1\n\nn\xc3\xa9\nabc");
+        let mut visited = Vec::new();
+        let mask_counts = generate_masks_with_visitor(
+            &mut wordlist,
+            &MaskConfig::default(),
+            &ReadOptions::default(),
+            &mut |word, mask| visited.push(format!("{}:{}", word, mask)),
+        )
+        .unwrap();
+
+        assert_eq!(visited, ["abc:lll", "Hello1:ulllld", "abc:lll"]);
+        assert_eq!(mask_counts.masks["lll"], 2);
+    }
+
+    #[test]
+    fn merged_mask_counts() {
+        let mut masks = HashMap::from([("ul".to_string(), 2)]);
+        merge_counts(
+            &mut masks,
+            HashMap::from([("ul".to_string(), 3), ("d".to_string(), 1)]),
+        );
+
+        assert_eq!(
+            masks,
+            HashMap::from([("ul".to_string(), 5), ("d".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn record_single_words() {
+        let config = MaskConfig::default();
+        let mut masks = HashMap::new();
+
+        assert_eq!(record_word(&mut masks, "abc", &config), Ok(()));
+        assert_eq!(record_word(&mut masks, "xyz", &config), Ok(()));
+        assert_eq!(record_word(&mut masks, "", &config), Ok(()));
+        assert_eq!(
+            record_word(&mut masks, "a\u{7f}", &config),
+            Err(MaskError::InvalidCharacter('\u{7f}'))
+        );
+        assert_eq!(masks, HashMap::from([("lll".to_string(), 2)]));
+    }
+
+    #[test]
+    fn lazy_masks() {
+        let wordlist = Cursor::new(b"Hello\n\nP\xc3\xa4ss\n12");
+        let config = MaskConfig::default();
+        let masks: Vec<_> = masks(wordlist, &config).map(|mask| mask.unwrap()).collect();
+
+        assert_eq!(masks.len(), 4);
+        assert_eq!(masks[0].as_deref(), Ok("ullll"));
+        assert_eq!(masks[1], Err(MaskError::EmptyWord));
+        assert!(masks[2].is_err());
+        assert_eq!(masks[3].as_deref(), Ok("dd"));
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let mut wordlist = Cursor::new(b"Hello\r\nFriend\r\n");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+
+        assert_eq!(mask_counts.masks["ullll"], 1);
+        assert_eq!(mask_counts.masks["ulllll"], 1);
+        assert_eq!(mask_counts.skipped.words, 0);
+    }
+
+    #[test]
+    fn masks_from_iterator() {
+        let mut wordlist = Cursor::new(b"Hello\nFriend\nPassword\nP@$$w0rd");
+        generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_counts_match_serial() {
+        let words: Vec<String> = (0..200_000)
+            .map(|index| match index % 4 {
+                0 => format!("Pass{}", index),
+                1 => format!("{}!", index),
+                2 => "\u{e9}t\u{e9}".to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        let wordlist = words.join("\n");
+        let config = MaskConfig::default();
+
+        let serial = generate_masks_from_bufreader(&mut Cursor::new(&wordlist), &config).unwrap();
+        let parallel = super::generate_masks_parallel(
+            &mut Cursor::new(&wordlist),
+            &config,
+            &super::ReadOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(parallel.masks, serial.masks);
+        assert_eq!(parallel.total_words, serial.total_words);
+        assert_eq!(parallel.skipped, serial.skipped);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_cap_keeps_most_frequent() {
+        let wordlist = "a\nAb\n1\n12\n12\nab\nab\nab\nxy\nxy\nxy";
+        let read_options = super::ReadOptions {
+            max_distinct_masks: Some(2),
+            ..super::ReadOptions::default()
+        };
+
+        for _ in 0..10 {
+            let mask_counts = super::generate_masks_parallel(
+                &mut Cursor::new(wordlist),
+                &MaskConfig::default(),
+                &read_options,
+            )
+            .unwrap();
+            assert_eq!(
+                mask_counts.masks,
+                HashMap::from([("ll".to_string(), 6), ("dd".to_string(), 2)])
+            );
+            assert_eq!(mask_counts.capped_words, 3);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn shared_counts_from_threads() {
+        let config = MaskConfig::default();
+        let counts = super::SharedMaskCounts::new();
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (counts, config) = (&counts, &config);
+                scope.spawn(move || {
+                    for index in 0..10_000 {
+                        let word = match index % 2 {
+                            0 => format!("Pass{}", thread),
+                            _ => format!("{}!", index % 10),
+                        };
+                        counts.add_word(&word, config).unwrap();
+                    }
+                    assert!(counts.add_word("\u{e9}", config).is_err());
+                });
+            }
+        });
+
+        let masks = counts.masks();
+        assert_eq!(masks.len(), 2);
+        assert_eq!(masks["ullld"], 40_000);
+        assert_eq!(masks["ds"], 40_000);
+        assert_eq!(counts.sort_masks(usize::MAX, &config)[0].count, 40_000);
+    }
+
+    #[test]
+    fn distinct_masks_cap() {
+        let wordlist = b"abc\nabcd\nxyz\nabcde\n123\nqwe\n";
+        let read_options = ReadOptions {
+            max_distinct_masks: Some(2),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(wordlist),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+
+        assert_eq!(mask_counts.masks.len(), 2);
+        assert_eq!(mask_counts.masks["lll"], 3);
+        assert_eq!(mask_counts.masks["llll"], 1);
+        assert_eq!(mask_counts.capped_words, 2);
+        assert_eq!(mask_counts.total_words, 6);
+
+        let options = ParseOptions {
+            read: read_options,
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+        assert_eq!(result.distinct_masks, 2);
+        assert!(result.capped_words >= 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_counts_accumulate() {
+        use super::{load_counts, save_counts};
+
+        let path = std::env::temp_dir().join("speedy_mask_saved_counts.json");
+        let config = MaskConfig::default();
+        let mask_counts =
+            generate_masks_from_bufreader(&mut Cursor::new(b"abc\nHello\nP\xc3\xa4ss"), &config)
+                .unwrap();
+        save_counts(&mask_counts, &path).unwrap();
+
+        let mut loaded_counts = load_counts(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded_counts.masks, mask_counts.masks);
+        assert_eq!(loaded_counts.skipped, mask_counts.skipped);
+
+        loaded_counts
+            .merge(generate_masks_from_bufreader(&mut Cursor::new(b"xyz"), &config).unwrap());
+        assert_eq!(loaded_counts.masks["lll"], 2);
+        assert_eq!(loaded_counts.total_words, 4);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_wordlist() {
+        use std::io::Write;
+
+        let directory = std::env::temp_dir();
+        let gzip_path = directory.join("speedy_mask_gzip_wordlist.txt.gz");
+        let fake_path = directory.join("speedy_mask_fake_wordlist.txt.gz");
+
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gzip_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"Hello\nFriend\nWorld").unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(&fake_path, b"Hello\nFriend\nWorld").unwrap();
+
+        let mask_counts = generate_masks_from_bufreader(
+            &mut super::open_wordlist(&gzip_path).unwrap(),
+            &MaskConfig::default(),
+        )
+        .unwrap();
+        let fake_error = super::open_wordlist(&fake_path).err().unwrap();
+
+        std::fs::remove_file(gzip_path).unwrap();
+        std::fs::remove_file(fake_path).unwrap();
+
+        assert_eq!(mask_counts.masks["ullll"], 2);
+        assert_eq!(fake_error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn weighted_files_saturate() {
+        let directory = std::env::temp_dir();
+        let first_path = directory.join("speedy_mask_weighted_first.txt");
+        let second_path = directory.join("speedy_mask_weighted_second.txt");
+        let huge_lines = format!("{0}\tabc\n{0}\tn\u{e9}", usize::MAX);
+        std::fs::write(&first_path, &huge_lines).unwrap();
+        std::fs::write(&second_path, &huge_lines).unwrap();
+        let options = ParseOptions {
+            read: ReadOptions {
+                weight_delimiter: Some('\t'),
+                ..ReadOptions::default()
+            },
+            ..ParseOptions::default()
+        };
+
+        let mask_counts = count_files(&[&first_path, &second_path], &options);
+        std::fs::remove_file(first_path).unwrap();
+        std::fs::remove_file(second_path).unwrap();
+
+        let mask_counts = mask_counts.unwrap();
+        assert_eq!(mask_counts.masks["lll"], usize::MAX);
+        assert_eq!(mask_counts.total_words, usize::MAX);
+        assert_eq!(mask_counts.skipped.words, usize::MAX);
+        assert_eq!(
+            mask_counts.skipped.invalid_characters[&'\u{e9}'],
+            usize::MAX
+        );
+
+        let mut masks = mask_counts.masks.clone();
+        merge_counts(&mut masks, mask_counts.masks);
+        assert_eq!(masks["lll"], usize::MAX);
+    }
+
+    #[test]
+    fn skipped_lines_name_their_file() {
+        let directory = std::env::temp_dir();
+        let first_path = directory.join("speedy_mask_skipped_first.txt");
+        let second_path = directory.join("speedy_mask_skipped_second.txt");
+        std::fs::write(&first_path, "abc\nn\u{e9}").unwrap();
+        std::fs::write(&second_path, "n\u{e9}\nabc").unwrap();
+
+        let mask_counts = count_files(&[&second_path, &first_path], &ParseOptions::default());
+        std::fs::remove_file(&first_path).unwrap();
+        std::fs::remove_file(&second_path).unwrap();
+
+        let skipped_lines = mask_counts.unwrap().skipped.lines;
+        assert_eq!(
+            skipped_lines,
+            [
+                SkippedLine {
+                    path: Some(second_path),
+                    line: 1
+                },
+                SkippedLine {
+                    path: Some(first_path),
+                    line: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_words() {
+        let mut wordlist = Cursor::new(b"aaa\naaa\nBbb\nBbb");
+        let read_options = ReadOptions {
+            dedup: true,
+            ..ReadOptions::default()
+        };
+        let mask_counts =
+            generate_masks_with_options(&mut wordlist, &MaskConfig::default(), &read_options)
+                .unwrap();
+
+        assert_eq!(mask_counts.masks.len(), 2);
+        assert_eq!(mask_counts.masks["lll"], 1);
+        assert_eq!(mask_counts.masks["ull"], 1);
+        assert_eq!(mask_counts.total_words, 2);
+    }
+
+    #[cfg(feature = "walkdir")]
+    #[test]
+    fn count_directory_files() {
+        let directory = std::env::temp_dir().join("speedy_mask_count_directory_files");
+        std::fs::create_dir_all(directory.join("nested/deeper")).unwrap();
+        std::fs::write(directory.join("a.txt"), "Hello\nabc\n").unwrap();
+        std::fs::write(directory.join("archive.bin"), b"abc\n\xff\xfe\n").unwrap();
+        std::fs::write(directory.join("nested/b.txt"), "xyz\n").unwrap();
+        std::fs::write(directory.join("nested/deeper/c.txt"), "123\n").unwrap();
+        let counts_at = |walk_depth| {
+            let options = ParseOptions {
+                read: ReadOptions {
+                    walk_depth: Some(walk_depth),
+                    ..ReadOptions::default()
+                },
+                ..ParseOptions::default()
+            };
+            count_files(&[&directory], &options).unwrap()
+        };
+
+        let mask_counts = counts_at(usize::MAX);
+        assert_eq!(mask_counts.masks["lll"], 2);
+        assert_eq!(mask_counts.masks["ddd"], 1);
+        assert_eq!(mask_counts.total_words, 4);
+        assert_eq!(mask_counts.skipped.binary_files, 1);
+        assert!(!counts_at(2).masks.contains_key("ddd"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn selection_coverage() {
+        let mut wordlist = Cursor::new(b"Hello\nWorld\nFriend\n\nP\xc3\xa4ss");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+
+        assert_eq!(mask_counts.total_words, 4);
+        assert_eq!(mask_counts.skipped.words, 1);
+        assert_eq!(mask_counts.skipped.invalid_characters[&'\u{e4}'], 1);
+        assert_eq!(
+            mask_counts.skipped.lines,
+            [SkippedLine {
+                path: None,
+                line: 5
+            }]
+        );
+        assert_eq!(mask_counts.coverage(&mask_list[..1]), 0.5);
+        assert_eq!(MaskCounts::default().coverage(&mask_list), 0.0);
+    }
+
+    #[test]
+    fn count_map_memory_estimate() {
+        let mut masks = HashMap::new();
+        let empty_memory = estimated_memory(&masks);
+        masks.insert("ullll".to_string(), 1);
+        masks.insert("lllllllldd".to_string(), 3);
+
+        let memory = estimated_memory(&masks);
+        assert!(memory >= empty_memory + masks.capacity() * 32 + 15);
+    }
+
+    #[test]
+    fn sampled_counts_are_scaled() {
+        let wordlist = b"abc\nHello\nabc\nWorld\nabc\n12\nn\xc3\xa9";
+        let read_options = ReadOptions {
+            sample_every: Some(2),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(wordlist),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+
+        assert_eq!(mask_counts.masks, HashMap::from([("lll".to_string(), 6)]));
+        assert_eq!(mask_counts.total_words, 8);
+        assert_eq!(mask_counts.skipped.words, 2);
+
+        let huge_weight = usize::MAX / 2;
+        let wordlist = format!("{0}\tabc\nx\n{0}\tabc\nx\nbad\nx\n{0}\tné", huge_weight);
+        let read_options = ReadOptions {
+            sample_every: Some(2),
+            weight_delimiter: Some('\t'),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(wordlist),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+        assert_eq!(mask_counts.masks["lll"], usize::MAX);
+        assert_eq!(mask_counts.total_words, usize::MAX);
+        assert_eq!(mask_counts.skipped.words, huge_weight * 2);
+        assert_eq!(mask_counts.skipped.malformed_lines, 2);
+    }
+
+    #[test]
+    fn long_lines_are_skipped() {
+        let wordlist = b"abc\r\nabcdefgh\nabcd\r\nabcde\n12";
+        let read_options = ReadOptions {
+            max_line_length: Some(4),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(wordlist),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            mask_counts.masks,
+            HashMap::from([
+                ("lll".to_string(), 1),
+                ("llll".to_string(), 1),
+                ("dd".to_string(), 1)
+            ])
+        );
+        assert_eq!(mask_counts.skipped.long_lines, 2);
+
+        let mut reader = io::BufReader::with_capacity(2, Cursor::new(&wordlist[..]));
+        let lines: Vec<_> = BoundedLines::new(&mut reader, Some(4))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            lines,
+            [
+                Some("abc".to_string()),
+                None,
+                Some("abcd".to_string()),
+                None,
+                Some("12".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn weighted_wordlist() {
+        let wordlist = b"3\tabc\n2\tHello\nx\tabc\nabc\n1\tn\xc3\xa9\n   4 abc";
+        let read_options = ReadOptions {
+            weight_delimiter: Some('\t'),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(wordlist),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+        assert_eq!(
+            mask_counts.masks,
+            HashMap::from([("lll".to_string(), 3), ("ullll".to_string(), 2)])
+        );
+        assert_eq!(mask_counts.total_words, 6);
+        assert_eq!(mask_counts.skipped.words, 1);
+        assert_eq!(mask_counts.skipped.malformed_lines, 3);
+
+        let read_options = ReadOptions {
+            weight_delimiter: Some(' '),
+            ..ReadOptions::default()
+        };
+        let mask_counts = generate_masks_with_options(
+            &mut Cursor::new(b"   4 abc\n 1 a b"),
+            &MaskConfig::default(),
+            &read_options,
+        )
+        .unwrap();
+        assert_eq!(
+            mask_counts.masks,
+            HashMap::from([("lll".to_string(), 4), ("lsl".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn byte_order_mark_skipped() {
+        let mut wordlist = Cursor::new(b"\xef\xbb\xbfHello\r\n\xef\xbb\xbfabc");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+
+        // only the leading mark is stripped, a later one is an invalid character
+        assert_eq!(mask_counts.masks, HashMap::from([("ullll".to_string(), 1)]));
+        assert_eq!(
+            mask_counts.skipped.invalid_characters,
+            BTreeMap::from([('\u{feff}', 1)])
+        );
+    }
+}
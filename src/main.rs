@@ -1,31 +1,1917 @@
 #![deny(clippy::all)]
 
-mod lib;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
 
-use clap::Parser;
-use std::io::Write;
-
-use crate::lib::parse_file;
+use speedy_mask::{
+    analyze_counts, analyze_files, count_files, estimated_memory, group_by_length,
+    length_distribution, observed_special_chars, open_wordlist, parse_files,
+    parse_files_separately, parse_hashcat_mask_with, parse_mask, position_charsets, position_stats,
+    read_mask_list, sample_words, stream_selected_masks, to_hcmask_line, uncovered_words,
+    verify_coverage, AnalysisResult, CharsetSizes, ComputedMask, CostMetric, CustomClass,
+    FileError, LetterMergeMode, MaskClass, MaskConfig, MaskConfigBuilder, MaskCounts, MaskFilter,
+    OnUnknown, ParseOptions, PolicyFilter, ReadOptions, SelectionStrategy, SkipReport, SortKey,
+    SpecialCharset, BUILTIN_CLASSES, DEFAULT_COST_EPSILON,
+};
+#[cfg(feature = "serde")]
+use speedy_mask::{combined_coverage, load_counts, position_fractions, save_counts};
 
 /// Parse provided file and print a list of masks up to provided space limit.
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None, arg_required_else_help = true)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// wordlist to parse
-    wordlist: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// options of the `masks` subcommand, which runs when no subcommand is given
+    #[clap(flatten)]
+    masks: MasksArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// print masks of the wordlists up to a space limit, the default
+    Masks(Box<MasksArgs>),
+    /// print the word length distribution or per-position class frequencies
+    Stats(StatsArgs),
+    /// print random words matching a mask
+    Expand(ExpandArgs),
+    /// print how much of the wordlists a list of masks covers
+    Coverage(CoverageArgs),
+}
+
+/// How characters are classified, shared by all subcommands.
+#[derive(Args)]
+struct ClassifyArgs {
+    /// characters classified as special, other symbols make a word invalid
+    #[clap(long)]
+    special_charset: Option<String>,
+
+    /// predefined special charset, instead of --special-charset
+    #[clap(
+        long,
+        value_enum,
+        value_name = "NAME",
+        conflicts_with = "special-charset"
+    )]
+    charset: Option<CharsetName>,
+
+    /// extra class checked before the built-in ones, e.g. `v=aeiou`
+    #[clap(long = "class", value_name = "LETTER=CHARSET", value_parser = parse_custom_class)]
+    custom_classes: Vec<CustomClass>,
+
+    /// classify uppercase letters as lowercase ones
+    #[clap(long)]
+    fold_case: bool,
+
+    /// classify letters of both cases as `a`, with a multiplier of 52 rather than the 26 of
+    /// --fold-case
+    #[clap(long, conflicts_with = "fold-case")]
+    any_letter: bool,
+
+    /// classify non-ASCII letters as `L` and numbers as `N`, keyspaces become estimates
+    #[clap(long)]
+    unicode: bool,
+
+    /// classify spaces and tabs as `w` instead of special and invalid characters
+    #[clap(long)]
+    whitespace: bool,
+
+    /// classify the digits starting a word as `p` and those ending it as `t`
+    #[clap(long)]
+    split_digits: bool,
+
+    /// characters matching no class: `error` drops their words as skipped, `skip` leaves them
+    /// out of the counts, `LETTER=KEYSPACE` classifies them, e.g. `e=1000` for emoji
+    #[clap(
+        long,
+        value_name = "ACTION",
+        value_parser = parse_on_unknown,
+        default_value = "error"
+    )]
+    on_unknown: OnUnknown,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// wordlists to parse, `-` reads the standard input
+    #[clap(required = true)]
+    wordlists: Vec<String>,
+
+    /// print per-position class frequencies instead of word lengths
+    #[clap(long, alias = "position-stats")]
+    positions: bool,
+
+    /// output of the per-position class frequencies
+    #[clap(long, value_enum, default_value_t = StatsOutput::Text, requires = "positions")]
+    output: StatsOutput,
+
+    #[clap(flatten)]
+    classify: ClassifyArgs,
+}
+
+#[derive(Args)]
+struct ExpandArgs {
+    /// native or hashcat mask to expand
+    mask: String,
+
+    /// number of distinct words to print, fewer when the keyspace is smaller
+    #[clap(short = 'n', long, default_value_t = 10)]
+    count: usize,
+
+    /// seed of the random words, the same seed giving the same words
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+
+    #[clap(flatten)]
+    classify: ClassifyArgs,
+}
+
+#[derive(Args)]
+struct CoverageArgs {
+    /// wordlists to parse, `-` reads the standard input
+    #[clap(required = true)]
+    wordlists: Vec<String>,
 
-    #[clap(short = 'l', default_value_t = usize::MAX)]
+    /// masks to check, one native or hashcat mask per line
+    #[clap(long, value_name = "FILE")]
+    masks: String,
+
+    /// count repeated words only once, at the cost of remembering every word
+    #[clap(long)]
+    dedup: bool,
+
+    #[clap(flatten)]
+    classify: ClassifyArgs,
+}
+
+#[derive(Args)]
+struct MasksArgs {
+    /// wordlists to parse, `-` reads the standard input
+    #[cfg_attr(not(feature = "serde"), clap(required = true))]
+    #[cfg_attr(feature = "serde", clap(required_unless_present = "load-counts"))]
+    wordlists: Vec<String>,
+
+    /// keyspace budget, with an optional K/M/G/T/P/E suffix, binary with Ki/Mi/...
+    #[clap(short = 'l', long, value_parser = parse_space_limit, default_value_t = usize::MAX)]
     space_limit: usize,
+
+    /// drop any mask whose own keyspace is larger, e.g. `1Ti` for masks too long to run, with
+    /// the suffixes of --space-limit
+    #[clap(long, value_name = "KEYSPACE", value_parser = parse_space_limit)]
+    max_mask_keyspace: Option<usize>,
+
+    /// discard masks shorter than this length
+    #[clap(long)]
+    min_length: Option<usize>,
+
+    /// discard masks longer than this length
+    #[clap(long)]
+    max_length: Option<usize>,
+
+    /// discard masks occurring fewer times than this
+    #[clap(long, default_value_t = 0)]
+    min_occurrence: usize,
+
+    #[clap(flatten)]
+    classify: ClassifyArgs,
+
+    /// mask notation used for output
+    #[clap(long, value_enum, default_value_t = MaskFormat::Native)]
+    format: MaskFormat,
+
+    /// print how much of the wordlist the selected masks cover
+    #[clap(long)]
+    coverage: bool,
+
+    /// print every mask regardless of the space limit, with aggregate numbers on stderr
+    #[clap(long)]
+    all: bool,
+
+    /// count repeated words only once, at the cost of remembering every word
+    #[clap(long)]
+    dedup: bool,
+
+    /// stop counting new masks past this many, bounding memory on untrusted input
+    #[clap(long, value_name = "N")]
+    max_distinct_masks: Option<usize>,
+
+    /// print the time needed to run the selected masks at this many hashes per second
+    #[clap(long, value_name = "HASHES_PER_SEC", value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// print how long reading, sorting and selecting took
+    #[clap(long)]
+    timings: bool,
+
+    /// print how many words were dropped and which characters caused it
+    #[clap(long)]
+    report_skipped: bool,
+
+    /// order of the printed masks
+    #[clap(long, value_enum, default_value_t = SortOrder::Cost)]
+    sort: SortOrder,
+
+    /// reverse the --sort order, e.g. with --top to keep the lowest cost masks
+    #[clap(long)]
+    reverse: bool,
+
+    /// how mask costs are computed for the cost order
+    #[clap(long, value_enum, default_value_t = Metric::CountPerKeyspace)]
+    cost_metric: Metric,
+
+    /// relative cost difference under which shorter masks are sorted first
+    #[clap(long, default_value_t = DEFAULT_COST_EPSILON)]
+    cost_epsilon: f64,
+
+    /// print the masks left out by the space limit or `--top` instead of the selected ones
+    #[clap(long)]
+    rejected: bool,
+
+    /// flag the masks with a keyspace over the space limit among the --rejected ones, sorted last
+    /// with a keyspace of usize::MAX
+    #[clap(long)]
+    keep_over_budget: bool,
+
+    /// keep only masks containing each of these classes, e.g. `uds`; the length range comes
+    /// from --min-length and --max-length
+    #[clap(long, value_name = "CLASSES", value_parser = parse_required_classes)]
+    require: Option<RequiredClasses>,
+
+    /// re-read the wordlists and print the words matched by none of the selected masks
+    #[clap(long, conflicts_with = "rejected")]
+    invert: bool,
+
+    /// draw the N most frequent masks, 10 by default, as bars scaled to the terminal width
+    #[clap(long, value_name = "N", conflicts_with = "invert")]
+    histogram: Option<Option<usize>>,
+
+    /// select masks out of each wordlist on its own before the combined selection
+    #[clap(long, conflicts_with_all = &["all", "invert"])]
+    per_file: bool,
+
+    /// print the text output in blocks of masks of the same length, each sorted by cost
+    #[clap(long)]
+    group_by_length: bool,
+
+    /// follow each mask of the text output with the share of the words covered by it and the
+    /// masks above
+    #[clap(long, conflicts_with_all = &["group-by-length", "template"])]
+    cumulative: bool,
+
+    /// print each selected text mask as soon as it is picked, flushing every line, without
+    /// --optimize coverage
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "all", "per-file", "invert", "histogram", "rejected", "group-by-length", "verify",
+            "timings", "output",
+        ]
+    )]
+    stream: bool,
+
+    /// leave out the closing summary of the run on stderr
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// read lines as COUNT<DELIMITER>WORD, e.g. from `sort | uniq -c`
+    #[clap(long)]
+    weighted: bool,
+
+    /// separator of the counts and words of --weighted lines
+    #[clap(long, value_name = "DELIMITER", default_value_t = '\t')]
+    weight_delimiter: char,
+
+    /// read only one line in N and scale the counts up, for a quick estimate of huge wordlists
+    #[clap(long, value_name = "N")]
+    sample: Option<usize>,
+
+    /// skip lines longer than BYTES without reading them whole, guarding against corrupt files
+    #[clap(long, value_name = "BYTES")]
+    max_line_length: Option<usize>,
+
+    /// print the estimated memory taken by the mask counts
+    #[clap(long)]
+    mem_report: bool,
+
+    /// size the `s` class after the special characters found in the wordlists, reading them twice
+    #[clap(long)]
+    seed_charset: bool,
+
+    /// multiplier of the `s` class in keyspaces, e.g. the symbols a target policy allows,
+    /// classification still following the special charset
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = parse_multiplier,
+        conflicts_with = "seed-charset"
+    )]
+    special_keyspace: Option<usize>,
+
+    /// read every file of the wordlist directories, binary ones being skipped
+    #[cfg(feature = "walkdir")]
+    #[clap(long, conflicts_with_all = &["invert", "verify", "seed-charset"])]
+    recursive: bool,
+
+    /// directory levels read by --recursive, 1 reading only the files directly inside
+    #[cfg(feature = "walkdir")]
+    #[clap(long, value_name = "N", requires = "recursive")]
+    max_depth: Option<usize>,
+
+    /// re-read the wordlists and count the words matched by the selected masks
+    #[clap(long, conflicts_with = "dedup")]
+    verify: bool,
+
+    /// leave out the masks listed in FILE, one native or hashcat mask per line
+    #[clap(long, value_name = "FILE")]
+    exclude_file: Option<String>,
+
+    /// keep only the first N selected masks
+    #[clap(long, value_name = "N")]
+    top: Option<usize>,
+
+    /// how masks are picked within the space limit
+    #[clap(long, value_enum, default_value_t = Optimize::Greedy)]
+    optimize: Optimize,
+
+    /// add the mask counts saved by a previous run to the wordlists ones
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "FILE")]
+    load_counts: Option<String>,
+
+    /// save the mask counts, merged with --load-counts, for a later run
+    #[cfg(feature = "serde")]
+    #[clap(long, value_name = "FILE")]
+    save_counts: Option<String>,
+
+    /// write masks to this file instead of stdout
+    #[clap(short = 'o', long)]
+    output_file: Option<String>,
+
+    /// line format of the text output, with {mask}, {count}, {size}, {cost} and {entropy}
+    /// placeholders, e.g. `{mask}:{count}`
+    #[clap(long, value_parser = parse_template)]
+    template: Option<Template>,
+
+    /// output layout
+    #[clap(long, value_enum, default_value_t = OutputKind::Text)]
+    output: OutputKind,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MaskFormat {
+    /// compact `l/u/d/s` masks, e.g. `ullll`
+    Native,
+    /// hashcat tokens, e.g. `?u?l?l?l?l`
+    Hashcat,
+    /// native then hashcat mask, separated by a tab
+    Both,
+    /// `?u?l?l?l,COUNT` lines, most frequent first, like the `.masks` files of PACK 0.0.4
+    /// statsgen read by maskgen and policygen
+    Pack,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortOrder {
+    /// highest occurrences per keyspace first
+    Cost,
+    /// most frequent first
+    Count,
+    /// smallest keyspace first
+    Size,
+    /// lexical order
+    Mask,
+}
+
+impl From<SortOrder> for SortKey {
+    fn from(sort_order: SortOrder) -> Self {
+        match sort_order {
+            SortOrder::Cost => SortKey::Cost,
+            SortOrder::Count => SortKey::Count,
+            SortOrder::Size => SortKey::Size,
+            SortOrder::Mask => SortKey::Mask,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CharsetName {
+    /// hashcat `?s`, ASCII punctuation and space (default)
+    HashcatS,
+    /// ASCII punctuation without space
+    AsciiSpecial,
+    /// symbols of the digit keys, `!@#$%^&*()`
+    TopRow,
+}
+
+impl From<CharsetName> for SpecialCharset {
+    fn from(charset_name: CharsetName) -> Self {
+        match charset_name {
+            CharsetName::HashcatS => SpecialCharset::HashcatS,
+            CharsetName::AsciiSpecial => SpecialCharset::AsciiSpecial,
+            CharsetName::TopRow => SpecialCharset::TopRow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Metric {
+    /// occurrences per candidate
+    CountPerKeyspace,
+    /// occurrences per bit of keyspace, favoring longer masks
+    CountPerBit,
+    /// occurrences per candidate computed in log space, ranking masks overflowing the keyspace
+    /// last instead of dropping them
+    LogCountPerKeyspace,
+}
+
+impl From<Metric> for CostMetric {
+    fn from(metric: Metric) -> Self {
+        match metric {
+            Metric::CountPerKeyspace => CostMetric::CountPerKeyspace,
+            Metric::CountPerBit => CostMetric::CountPerLog2Keyspace,
+            Metric::LogCountPerKeyspace => CostMetric::Log2CountPerKeyspace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Optimize {
+    /// take masks in sorted order while they fit, fast
+    Greedy,
+    /// maximize the number of covered words
+    Coverage,
+}
+
+impl From<Optimize> for SelectionStrategy {
+    fn from(optimize: Optimize) -> Self {
+        match optimize {
+            Optimize::Greedy => SelectionStrategy::Greedy,
+            Optimize::Coverage => SelectionStrategy::Coverage,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputKind {
+    /// one mask per line
+    Text,
+    /// comma separated values with a header row
+    Csv,
+    /// array of masks with a summary object
+    #[cfg(feature = "serde")]
+    Json,
+    /// hashcat .hcmask lines, with custom charsets of the characters seen at each position
+    Hcmask,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsOutput {
+    /// aligned percentages table
+    Text,
+    /// array of per-position class fractions normalized by the words reaching the position
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// Parse a keyspace like `500M` or `4Gi`, suffixes being case insensitive.
+fn parse_space_limit(value: &str) -> Result<usize, String> {
+    const SUFFIXES: [&str; 6] = ["k", "m", "g", "t", "p", "e"];
+
+    let value = value.trim();
+    let digits_end = value
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, suffix) = value.split_at(digits_end);
+    let number: usize = digits
+        .parse()
+        .map_err(|_| format!("invalid number in '{}'", value))?;
+
+    let suffix = suffix.to_ascii_lowercase();
+    let (unit, base) = match suffix.strip_suffix('i') {
+        Some(unit) => (unit, 1024usize),
+        None => (suffix.as_str(), 1000usize),
+    };
+    let multiplier = if unit.is_empty() && base == 1000 {
+        1
+    } else {
+        let exponent = SUFFIXES
+            .iter()
+            .position(|&known| known == unit)
+            .ok_or_else(|| format!("invalid suffix '{}'", &value[digits_end..]))?;
+        base.pow(exponent as u32 + 1)
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{}' exceeds the largest keyspace {}", value, usize::MAX))
+}
+
+/// Classes of `--require`, wrapped so clap takes them as a single value.
+#[derive(Clone)]
+struct RequiredClasses(Vec<MaskClass>);
+
+fn parse_required_classes(value: &str) -> Result<RequiredClasses, String> {
+    parse_mask(value)
+        .map(RequiredClasses)
+        .map_err(|error| error.to_string())
+}
+
+/// Line format of `--template`, parsed once so unknown placeholders fail at startup.
+#[derive(Clone, Debug, PartialEq)]
+struct Template(Vec<TemplatePart>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Mask,
+    Count,
+    Size,
+    Cost,
+    Entropy,
+}
+
+impl Template {
+    fn render(&self, mask: &ComputedMask, format: MaskFormat) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(literal) => literal.clone(),
+                TemplatePart::Mask => render_mask(mask, format),
+                TemplatePart::Count => mask.count.to_string(),
+                TemplatePart::Size => mask.size.to_string(),
+                TemplatePart::Cost => mask.cost.to_string(),
+                TemplatePart::Entropy => format!("{:.2}", mask.entropy_bits),
+            })
+            .collect()
+    }
+}
+
+/// Parse a template like `{mask}:{count}`, `{{` and `}}` standing for literal braces.
+fn parse_template(value: &str) -> Result<Template, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = value.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed placeholder in '{}'", value))?;
+                let part = match &rest[..end] {
+                    "mask" => TemplatePart::Mask,
+                    "count" => TemplatePart::Count,
+                    "size" => TemplatePart::Size,
+                    "cost" => TemplatePart::Cost,
+                    "entropy" => TemplatePart::Entropy,
+                    placeholder => return Err(format!("unknown placeholder {{{}}}", placeholder)),
+                };
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => return Err(format!("unmatched '}}' in '{}'", value)),
+            _ => literal.push(char),
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(Template(parts))
+}
+
+/// Parse a `LETTER=CHARSET` class definition.
+fn parse_custom_class(value: &str) -> Result<CustomClass, String> {
+    let (letter, charset) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected LETTER=CHARSET, got '{}'", value))?;
+
+    Ok(CustomClass::new(free_class_letter(letter)?, charset))
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!(
+            "'{}' is not a positive number of hashes per second",
+            value
+        )),
+    }
+}
+
+/// Keyspace multiplier of a class, which must be positive.
+fn parse_multiplier(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(multiplier) if multiplier > 0 => Ok(multiplier),
+        _ => Err(format!("'{}' is not a positive keyspace", value)),
+    }
+}
+
+fn parse_on_unknown(value: &str) -> Result<OnUnknown, String> {
+    match value {
+        "error" => Ok(OnUnknown::Error),
+        "skip" => Ok(OnUnknown::Skip),
+        _ => {
+            let (letter, keyspace) = value.split_once('=').ok_or_else(|| {
+                format!("expected error, skip or LETTER=KEYSPACE, got '{}'", value)
+            })?;
+            Ok(OnUnknown::Class(
+                free_class_letter(letter)?,
+                parse_multiplier(keyspace)?,
+            ))
+        }
+    }
+}
+
+/// Letter of a class, which must be a single ASCII letter unused by the built-in classes.
+fn free_class_letter(letter: &str) -> Result<char, String> {
+    let mut letters = letter.chars();
+
+    match (letters.next(), letters.next()) {
+        (Some(letter), None)
+            if letter.is_ascii_alphabetic() && !BUILTIN_CLASSES.contains(letter) =>
+        {
+            Ok(letter)
+        }
+        _ => Err(format!(
+            "'{}' is not a free class letter, built-in ones are l, u, d, s, L, N, w, p, t and a",
+            letter
+        )),
+    }
+}
+
+fn config_builder(classify: &ClassifyArgs) -> MaskConfigBuilder {
+    let mut builder = MaskConfig::builder()
+        .letter_merge_mode(if classify.any_letter {
+            LetterMergeMode::AnyLetter
+        } else if classify.fold_case {
+            LetterMergeMode::MergedLetter
+        } else {
+            LetterMergeMode::Separate
+        })
+        .unicode(classify.unicode)
+        .whitespace(classify.whitespace)
+        .split_digits(classify.split_digits)
+        .on_unknown(classify.on_unknown);
+
+    if let Some(special_charset) = &classify.special_charset {
+        builder = builder.special_charset(special_charset);
+    }
+    if let Some(charset) = classify.charset {
+        builder = builder.special_charset(SpecialCharset::from(charset).charset());
+    }
+
+    for custom_class in &classify.custom_classes {
+        builder = builder.custom_class(custom_class.clone());
+    }
+
+    builder
+}
+
+fn mask_config(args: &MasksArgs) -> MaskConfig {
+    let mut builder = config_builder(&args.classify)
+        .cost_epsilon(args.cost_epsilon)
+        .cost_metric(args.cost_metric.into());
+    if let Some(special_keyspace) = args.special_keyspace {
+        builder = builder.special_keyspace(special_keyspace);
+    }
+    builder.build()
+}
+
+/// Same as [`mask_config`], the `s` multiplier being the number of distinct special characters
+/// of the wordlists with --seed-charset, found in a first reading pass.
+fn seeded_mask_config(args: &MasksArgs) -> MaskConfig {
+    let config = mask_config(args);
+    if !args.seed_charset {
+        return config;
+    }
+    if args.wordlists.iter().any(|wordlist| wordlist == "-") {
+        eprintln!("--seed-charset reads the wordlists twice, the standard input cannot be used");
+        process::exit(2);
+    }
+
+    let mut special_chars = BTreeSet::new();
+    for wordlist in &args.wordlists {
+        special_chars.extend(read_or_exit(
+            open_wordlist(wordlist).and_then(|reader| observed_special_chars(reader, &config)),
+            wordlist,
+        ));
+    }
+    eprintln!(
+        "special charset seeded from the wordlists: {}",
+        special_chars.iter().collect::<String>()
+    );
+
+    let charset_sizes = CharsetSizes {
+        special: special_chars.len().max(1),
+        ..config.charset_sizes().clone()
+    };
+    config_builder(&args.classify)
+        .charset_sizes(charset_sizes)
+        .cost_epsilon(args.cost_epsilon)
+        .cost_metric(args.cost_metric.into())
+        .build()
+}
+
+fn render_mask(mask: &ComputedMask, format: MaskFormat) -> String {
+    match format {
+        MaskFormat::Native => mask.to_string(),
+        MaskFormat::Hashcat | MaskFormat::Pack => mask.to_hashcat_mask(),
+        MaskFormat::Both => format!("{}\t{}", mask, mask.to_hashcat_mask()),
+    }
+}
+
+/// Shorten large counts, e.g. `1234567` becomes `1.2M`.
+fn human_count(count: usize) -> String {
+    const UNITS: [(f64, &str); 3] = [(1e9, "G"), (1e6, "M"), (1e3, "K")];
+
+    for (scale, unit) in UNITS {
+        if count as f64 >= scale {
+            return format!("{:.1}{}", count as f64 / scale, unit);
+        }
+    }
+
+    count.to_string()
+}
+
+/// Express a duration in the largest unit it reaches, e.g. `93600` seconds become `1.1 days`.
+fn human_duration(seconds: f64) -> String {
+    const UNITS: [(f64, &str); 4] = [
+        (365.25 * 86400.0, "years"),
+        (86400.0, "days"),
+        (3600.0, "hours"),
+        (60.0, "minutes"),
+    ];
+
+    for (scale, unit) in UNITS {
+        if seconds >= scale {
+            return format!("{:.1} {}", seconds / scale, unit);
+        }
+    }
+
+    format!("{:.1} seconds", seconds)
+}
+
+fn write_length_stats<W: Write>(
+    out: &mut W,
+    lengths_counts: BTreeMap<usize, usize>,
+) -> io::Result<()> {
+    let total_words: usize = lengths_counts.values().sum();
+    let mut lengths_counts: Vec<(usize, usize)> = lengths_counts.into_iter().collect();
+    lengths_counts.sort_by(|(_, count_0), (_, count_1)| count_1.cmp(count_0));
+
+    for (length, count) in lengths_counts {
+        let percentage = count as f64 * 100.0 / total_words as f64;
+        writeln!(out, "{:>4}: {:>6.2}% ({})", length, percentage, count)?;
+    }
+
+    Ok(())
+}
+
+fn write_position_stats<W: Write>(
+    out: &mut W,
+    positions_counts: &[HashMap<char, usize>],
+) -> io::Result<()> {
+    let classes: BTreeSet<char> = positions_counts
+        .iter()
+        .flat_map(|classes_counts| classes_counts.keys().copied())
+        .collect();
+
+    write!(out, "{:>4}", "pos")?;
+    for class in &classes {
+        write!(out, " {:>7}", class)?;
+    }
+    writeln!(out)?;
+
+    for (position, classes_counts) in positions_counts.iter().enumerate() {
+        let position_words: usize = classes_counts.values().sum();
+
+        write!(out, "{:>4}", position)?;
+        for class in &classes {
+            let count = classes_counts.get(class).copied().unwrap_or(0);
+            write!(
+                out,
+                " {:>6.2}%",
+                count as f64 * 100.0 / position_words as f64
+            )?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn write_position_fractions<W: Write>(
+    out: &mut W,
+    positions_counts: &[HashMap<char, usize>],
+) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &position_fractions(positions_counts))?;
+    writeln!(out)
+}
+
+fn write_skip_report(skipped: &SkipReport) {
+    eprintln!("skipped {} words with invalid characters", skipped.words);
+    if skipped.malformed_lines > 0 {
+        eprintln!(
+            "skipped {} lines without a valid count",
+            skipped.malformed_lines
+        );
+    }
+    if skipped.long_lines > 0 {
+        eprintln!("skipped {} lines that were too long", skipped.long_lines);
+    }
+    if skipped.binary_files > 0 {
+        eprintln!("skipped {} files that are not text", skipped.binary_files);
+    }
+    if !skipped.lines.is_empty() {
+        let lines: Vec<String> = skipped
+            .lines
+            .iter()
+            .map(|skipped_line| match &skipped_line.path {
+                Some(path) => format!("{}:{}", path.display(), skipped_line.line),
+                None => skipped_line.line.to_string(),
+            })
+            .collect();
+        eprintln!("first skipped lines: {}", lines.join(", "));
+    }
+
+    for (char, count) in &skipped.invalid_characters {
+        eprintln!("{:>8} {:?} ({})", count, char, char.escape_unicode());
+    }
+}
+
+const DEFAULT_HISTOGRAM_MASKS: usize = 10;
+
+/// Width of the terminal from `COLUMNS`, 80 columns when unknown.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Draw the `top` most frequent masks as bars, the longest one filling `width` along with the
+/// mask and its count.
+fn write_histogram<W: Write>(
+    out: &mut W,
+    masks: &[ComputedMask],
+    top: usize,
+    width: usize,
+    format: MaskFormat,
+) -> io::Result<()> {
+    let mut masks = masks.to_vec();
+    SortKey::Count.sort(&mut masks);
+    masks.truncate(top);
+
+    let labels: Vec<String> = masks.iter().map(|mask| render_mask(mask, format)).collect();
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+    let max_count = masks.first().map_or(0, |mask| mask.count);
+    let count_width = max_count.to_string().len();
+    let bar_width = width.saturating_sub(label_width + count_width + 2).max(1);
+
+    for (mask, label) in masks.iter().zip(&labels) {
+        let bar_length = (mask.count as f64 / max_count as f64 * bar_width as f64).round() as usize;
+        writeln!(
+            out,
+            "{:<label_width$} {} {}",
+            label,
+            "█".repeat(bar_length.max(1)),
+            mask.count,
+            label_width = label_width
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write `masks` by length, each block under a header with its subtotals.
+fn write_grouped_text<W: Write>(
+    out: &mut W,
+    masks: &[ComputedMask],
+    format: MaskFormat,
+    template: Option<&Template>,
+) -> io::Result<()> {
+    for (length, group) in group_by_length(masks) {
+        let count = group
+            .iter()
+            .fold(0usize, |total, mask| total.saturating_add(mask.count));
+        let keyspace = group
+            .iter()
+            .fold(0usize, |total, mask| total.saturating_add(mask.size));
+        writeln!(
+            out,
+            "# length {}: {} masks, {} words, keyspace {}",
+            length,
+            group.len(),
+            count,
+            keyspace
+        )?;
+        write_text(out, &group, format, template)?;
+    }
+
+    Ok(())
+}
+
+fn write_text<W: Write>(
+    out: &mut W,
+    masks: &[ComputedMask],
+    format: MaskFormat,
+    template: Option<&Template>,
+) -> io::Result<()> {
+    if let (MaskFormat::Pack, None) = (format, template) {
+        return write_pack(out, masks);
+    }
+
+    for mask in masks {
+        match template {
+            Some(template) => writeln!(out, "{}", template.render(mask, format))?,
+            None => writeln!(out, "{}", render_mask(mask, format))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `masks` one per line, tab separated from the running coverage of `total_words`.
+fn write_cumulative<W: Write>(
+    out: &mut W,
+    masks: &[ComputedMask],
+    format: MaskFormat,
+    total_words: usize,
+) -> io::Result<()> {
+    let mut covered_words = 0usize;
+
+    for mask in masks {
+        covered_words = covered_words.saturating_add(mask.count);
+        let percentage = if total_words == 0 {
+            0.0
+        } else {
+            covered_words as f64 * 100.0 / total_words as f64
+        };
+        writeln!(out, "{}\t{:.2}%", render_mask(mask, format), percentage)?;
+    }
+
+    Ok(())
+}
+
+/// Write `masks` as PACK statsgen does, by decreasing occurrences then mask.
+fn write_pack<W: Write>(out: &mut W, masks: &[ComputedMask]) -> io::Result<()> {
+    let mut masks: Vec<&ComputedMask> = masks.iter().collect();
+    masks.sort_by(|mask_0, mask_1| {
+        mask_1
+            .count
+            .cmp(&mask_0.count)
+            .then_with(|| mask_0.mask.cmp(&mask_1.mask))
+    });
+
+    for mask in masks {
+        writeln!(out, "{},{}", mask.to_hashcat_mask(), mask.count)?;
+    }
+
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv<W: Write>(out: &mut W, masks: &[ComputedMask], format: MaskFormat) -> io::Result<()> {
+    writeln!(out, "mask,length,keyspace,count,cost")?;
+
+    for mask in masks {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            csv_field(&render_mask(mask, format)),
+            mask.mask.len(),
+            mask.size,
+            mask.count,
+            mask.cost
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write `masks` in the requested layout, the JSON summary describing `selected`.
+#[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+fn write_masks<W: Write>(
+    out: &mut W,
+    args: &MasksArgs,
+    masks: &[ComputedMask],
+    selected: &[ComputedMask],
+    used_space: usize,
+    total_words: usize,
+) -> io::Result<()> {
+    match args.output {
+        OutputKind::Text if args.group_by_length => {
+            write_grouped_text(out, masks, args.format, args.template.as_ref())
+        }
+        OutputKind::Text if args.cumulative => {
+            write_cumulative(out, masks, args.format, total_words)
+        }
+        OutputKind::Text => write_text(out, masks, args.format, args.template.as_ref()),
+        OutputKind::Csv => write_csv(out, masks, args.format),
+        #[cfg(feature = "serde")]
+        OutputKind::Json => write_json(out, masks, selected, used_space, total_words, args.format),
+        OutputKind::Hcmask => write_hcmask(out, args, masks),
+    }
+    .and_then(|_| out.flush())
+}
+
+/// Write `masks` along with a summary of the `selected` ones.
+#[cfg(feature = "serde")]
+fn write_json<W: Write>(
+    out: &mut W,
+    masks: &[ComputedMask],
+    selected: &[ComputedMask],
+    used_space: usize,
+    total_words: usize,
+    format: MaskFormat,
+) -> io::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Summary {
+        used_space: usize,
+        total_words: usize,
+        coverage: f64,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Report {
+        masks: Vec<ComputedMask>,
+        summary: Summary,
+    }
+
+    let report = Report {
+        masks: masks
+            .iter()
+            .map(|mask| ComputedMask {
+                mask: render_mask(mask, format),
+                ..mask.clone()
+            })
+            .collect(),
+        summary: Summary {
+            used_space,
+            total_words,
+            coverage: combined_coverage(selected, total_words),
+        },
+    };
+
+    serde_json::to_writer_pretty(&mut *out, &report)?;
+    writeln!(out)
+}
+
+/// Closing line of a run, giving what the selection achieved on the wordlists.
+fn summary_line(
+    total_words: usize,
+    distinct_masks: usize,
+    selected_masks: usize,
+    used_space: u128,
+    coverage: f64,
+) -> String {
+    format!(
+        "{} passwords, {} distinct masks, {} selected with a keyspace of {}, covering {:.1}%",
+        human_count(total_words),
+        distinct_masks,
+        selected_masks,
+        used_space,
+        coverage * 100.0
+    )
+}
+
+/// Value of a wordlist reading, exiting with the failing path otherwise.
+fn or_exit<T>(result: Result<T, FileError>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    })
+}
+
+/// Value of a pass over `wordlist`, exiting with its path otherwise.
+fn read_or_exit<T>(result: io::Result<T>, wordlist: &str) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("cannot read {}: {}", wordlist, error);
+        process::exit(1);
+    })
+}
+
+/// Analyze the wordlists counts, reporting their memory and accumulating them as requested.
+fn counted_result(args: &MasksArgs, options: &ParseOptions) -> AnalysisResult {
+    analyze_counts(wordlist_counts(args, options), options)
+}
+
+/// Wordlists counts, reporting their memory and accumulating them as requested.
+fn wordlist_counts(args: &MasksArgs, options: &ParseOptions) -> MaskCounts {
+    #[cfg(feature = "serde")]
+    let mask_counts = accumulated_counts(args, options);
+    #[cfg(not(feature = "serde"))]
+    let mask_counts = or_exit(count_files(&args.wordlists, options));
+
+    if args.mem_report {
+        eprintln!(
+            "the counts of {} distinct masks take about {}B of memory",
+            mask_counts.masks.len(),
+            human_count(estimated_memory(&mask_counts.masks))
+        );
+    }
+
+    mask_counts
+}
+
+/// Print the selected masks as they are picked, for a prompt output on huge wordlists.
+fn run_streamed<W: Write>(out: &mut W, args: &MasksArgs, options: &ParseOptions) {
+    if let SelectionStrategy::Coverage = options.strategy {
+        eprintln!("--stream picks masks greedily and cannot be used with --optimize coverage");
+        process::exit(2);
+    }
+
+    let mask_counts = wordlist_counts(args, options);
+    let mut selected_masks = 0;
+    let mut used_space = 0usize;
+    let mut covered_words = 0usize;
+    let written = stream_selected_masks(&mask_counts, options).try_for_each(|mask| {
+        selected_masks += 1;
+        used_space += mask.size;
+        covered_words = covered_words.saturating_add(mask.count);
+        match (&args.template, args.format) {
+            (Some(template), _) => writeln!(out, "{}", template.render(&mask, args.format))?,
+            (None, _) if args.cumulative => writeln!(
+                out,
+                "{}\t{:.2}%",
+                render_mask(&mask, args.format),
+                covered_words as f64 * 100.0 / mask_counts.total_words as f64
+            )?,
+            (None, MaskFormat::Pack) => writeln!(out, "{},{}", mask.to_hashcat_mask(), mask.count)?,
+            (None, _) => writeln!(out, "{}", render_mask(&mask, args.format))?,
+        }
+        out.flush()
+    });
+    exit_on_write_error(written, args);
+
+    if selected_masks == 0 && !mask_counts.masks.is_empty() {
+        eprintln!(
+            "none of the {} distinct masks passed the filters and space limit",
+            mask_counts.masks.len()
+        );
+    }
+
+    let coverage = if mask_counts.total_words == 0 {
+        0.0
+    } else {
+        covered_words as f64 / mask_counts.total_words as f64
+    };
+    if args.coverage {
+        eprintln!(
+            "selected {} masks covering {:.1}% of {} passwords",
+            selected_masks,
+            coverage * 100.0,
+            human_count(mask_counts.total_words)
+        );
+    }
+
+    if let Some(rate) = args.rate {
+        eprintln!(
+            "estimated run time: {} for a keyspace of {} at {} H/s",
+            human_duration(used_space as f64 / rate),
+            used_space,
+            rate
+        );
+    }
+
+    if args.report_skipped {
+        write_skip_report(&mask_counts.skipped);
+    }
+
+    if !args.quiet {
+        eprintln!(
+            "{}",
+            summary_line(
+                mask_counts.total_words,
+                mask_counts.masks.len(),
+                selected_masks,
+                used_space as u128,
+                coverage
+            )
+        );
+    }
+}
+
+/// Wordlists counts merged with the loaded ones, saved on the way.
+#[cfg(feature = "serde")]
+fn accumulated_counts(args: &MasksArgs, options: &ParseOptions) -> MaskCounts {
+    let mut mask_counts = match &args.load_counts {
+        Some(path) => load_counts(path).unwrap_or_else(|error| {
+            eprintln!("cannot load {}: {}", path, error);
+            process::exit(1);
+        }),
+        None => MaskCounts::default(),
+    };
+    mask_counts.merge(or_exit(count_files(&args.wordlists, options)));
+
+    if let Some(path) = &args.save_counts {
+        if let Err(error) = save_counts(&mask_counts, path) {
+            eprintln!("cannot write {}: {}", path, error);
+            process::exit(1);
+        }
+    }
+
+    mask_counts
+}
+
+/// How the wordlists are read, the same in the counting pass and the passes re-reading them.
+fn read_options(args: &MasksArgs) -> ReadOptions {
+    ReadOptions {
+        dedup: args.dedup,
+        progress: true,
+        max_distinct_masks: args.max_distinct_masks,
+        sample_every: args.sample,
+        weight_delimiter: args.weighted.then_some(args.weight_delimiter),
+        max_line_length: args.max_line_length,
+        #[cfg(feature = "walkdir")]
+        walk_depth: args
+            .recursive
+            .then_some(args.max_depth.unwrap_or(usize::MAX)),
+    }
+}
+
+fn excluded_masks(args: &MasksArgs) -> HashSet<String> {
+    let exclude_file = match &args.exclude_file {
+        Some(exclude_file) => exclude_file,
+        None => return HashSet::new(),
+    };
+
+    let config = mask_config(args);
+    match open_wordlist(exclude_file).and_then(|reader| read_mask_list(reader, &config)) {
+        Ok(excluded_masks) => excluded_masks,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", exclude_file, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn write_uncovered_words<W: Write>(
+    out: &mut W,
+    args: &MasksArgs,
+    masks: &[ComputedMask],
+) -> io::Result<()> {
+    let config = mask_config(args);
+    let read_options = read_options(args);
+    let selected_masks: HashSet<String> = masks.iter().map(|mask| mask.mask.clone()).collect();
+    for wordlist in &args.wordlists {
+        if wordlist == "-" {
+            eprintln!("the standard input cannot be read again, its uncovered words are left out");
+            continue;
+        }
+        let reader = read_or_exit(open_wordlist(wordlist), wordlist);
+        for word in uncovered_words(reader, &selected_masks, &config, &read_options) {
+            writeln!(out, "{}", read_or_exit(word, wordlist))?;
+        }
+    }
+    out.flush()
+}
+
+/// Re-read the wordlists for the characters seen at each position of `masks`, masks of the
+/// standard input keeping their plain hashcat form.
+fn write_hcmask<W: Write>(out: &mut W, args: &MasksArgs, masks: &[ComputedMask]) -> io::Result<()> {
+    let config = mask_config(args);
+    let read_options = read_options(args);
+    let selected_masks: HashSet<String> = masks.iter().map(|mask| mask.mask.clone()).collect();
+    let mut charsets: HashMap<String, Vec<BTreeSet<char>>> = HashMap::new();
+    for wordlist in &args.wordlists {
+        if wordlist == "-" {
+            eprintln!("the standard input cannot be read again, its characters are left out");
+            continue;
+        }
+        if Path::new(wordlist).is_dir() {
+            eprintln!("{} is a directory, its characters are left out", wordlist);
+            continue;
+        }
+        let wordlist_charsets = read_or_exit(
+            open_wordlist(wordlist).and_then(|reader| {
+                position_charsets(reader, &selected_masks, &config, &read_options)
+            }),
+            wordlist,
+        );
+        for (mask, position_chars) in wordlist_charsets {
+            let merged_chars = charsets
+                .entry(mask)
+                .or_insert_with(|| vec![BTreeSet::new(); position_chars.len()]);
+            for (merged, chars) in merged_chars.iter_mut().zip(position_chars) {
+                merged.extend(chars);
+            }
+        }
+    }
+
+    for mask in masks {
+        let position_chars = charsets.get(&mask.mask).map_or(&[][..], Vec::as_slice);
+        writeln!(
+            out,
+            "{}",
+            to_hcmask_line(&mask.mask, position_chars, config.charset_sizes())
+        )?;
+    }
+    Ok(())
+}
+
+fn write_verification(args: &MasksArgs, masks: &[ComputedMask]) {
+    let config = mask_config(args);
+    let read_options = read_options(args);
+    let mut matched_words = 0usize;
+    for wordlist in &args.wordlists {
+        if wordlist == "-" {
+            eprintln!(
+                "the standard input cannot be read again, it is left out of the verification"
+            );
+            continue;
+        }
+        matched_words = matched_words.saturating_add(read_or_exit(
+            open_wordlist(wordlist)
+                .and_then(|reader| verify_coverage(reader, masks, &config, &read_options)),
+            wordlist,
+        ));
+    }
+
+    eprintln!(
+        "verification: {} words matched by the selected masks, {} counted",
+        matched_words,
+        masks
+            .iter()
+            .fold(0usize, |total, mask| total.saturating_add(mask.count))
+    );
+}
+
+/// Write the masks of `result` under a header naming them.
+fn write_section<W: Write>(out: &mut W, args: &MasksArgs, name: &str, result: &AnalysisResult) {
+    let written = writeln!(
+        out,
+        "# {}: {} passwords, {} distinct masks, {} selected covering {:.1}%",
+        name,
+        human_count(result.total_words),
+        result.distinct_masks,
+        result.masks.len(),
+        result.coverage() * 100.0
+    )
+    .and_then(|_| {
+        write_masks(
+            out,
+            args,
+            if args.rejected {
+                &result.rejected
+            } else {
+                &result.masks
+            },
+            &result.masks,
+            result.used_space,
+            result.total_words,
+        )
+    });
+    exit_on_write_error(written, args);
+}
+
+fn exit_on_write_error(written: io::Result<()>, args: &MasksArgs) {
+    // a closed stdout (e.g. piped into `head`) simply ends the output
+    if let (Err(error), Some(output_file)) = (written, &args.output_file) {
+        eprintln!("cannot write {}: {}", output_file, error);
+        process::exit(1);
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
-    let (sorted_masks, _) = parse_file(cli.wordlist, cli.space_limit).unwrap();
-    let mut stdout = std::io::stdout();
 
-    for mask in sorted_masks {
-        if writeln!(&mut stdout, "{}", mask).is_err() {
-            return;
+    match cli.command.unwrap_or(Command::Masks(Box::new(cli.masks))) {
+        Command::Masks(args) => run_masks(*args),
+        Command::Stats(args) => run_stats(args),
+        Command::Expand(args) => run_expand(args),
+        Command::Coverage(args) => run_coverage(args),
+    }
+}
+
+fn run_stats(args: StatsArgs) {
+    if args.positions {
+        let config = config_builder(&args.classify).build();
+        let mut positions_counts: Vec<HashMap<char, usize>> = Vec::new();
+        for wordlist in &args.wordlists {
+            let wordlist_counts = read_or_exit(
+                open_wordlist(wordlist).and_then(|mut reader| position_stats(&mut reader, &config)),
+                wordlist,
+            );
+            if positions_counts.len() < wordlist_counts.len() {
+                positions_counts.resize_with(wordlist_counts.len(), HashMap::new);
+            }
+            for (position, classes_counts) in wordlist_counts.into_iter().enumerate() {
+                for (class, count) in classes_counts {
+                    *positions_counts[position].entry(class).or_insert(0) += count;
+                }
+            }
         }
+        let _ = match args.output {
+            StatsOutput::Text => write_position_stats(&mut io::stdout(), &positions_counts),
+            #[cfg(feature = "serde")]
+            StatsOutput::Json => write_position_fractions(&mut io::stdout(), &positions_counts),
+        };
+        return;
+    }
+
+    let mut lengths_counts = BTreeMap::new();
+    for wordlist in &args.wordlists {
+        let wordlist_lengths = read_or_exit(
+            open_wordlist(wordlist).and_then(|mut reader| length_distribution(&mut reader)),
+            wordlist,
+        );
+        for (length, count) in wordlist_lengths {
+            *lengths_counts.entry(length).or_insert(0) += count;
+        }
+    }
+    let _ = write_length_stats(&mut io::stdout(), lengths_counts);
+}
+
+fn run_expand(args: ExpandArgs) {
+    let config = config_builder(&args.classify).build();
+    let mask = if args.mask.starts_with('?') {
+        parse_hashcat_mask_with(&args.mask, &config)
+    } else {
+        Ok(args.mask.clone())
+    };
+
+    match mask.and_then(|mask| sample_words(&mask, args.count, &config, args.seed)) {
+        Ok(words) => {
+            let mut out = io::stdout().lock();
+            for word in words {
+                if writeln!(out, "{}", word).is_err() {
+                    return;
+                }
+            }
+        }
+        Err(error) => {
+            eprintln!("cannot expand {}: {}", args.mask, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_coverage(args: CoverageArgs) {
+    let config = config_builder(&args.classify).build();
+    let masks = match open_wordlist(&args.masks).and_then(|reader| read_mask_list(reader, &config))
+    {
+        Ok(masks) => masks,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", args.masks, error);
+            process::exit(1);
+        }
+    };
+    let options = ParseOptions {
+        config,
+        read: ReadOptions {
+            dedup: args.dedup,
+            progress: true,
+            ..ReadOptions::default()
+        },
+        ..ParseOptions::default()
+    };
+    let mask_counts = or_exit(count_files(&args.wordlists, &options));
+    let covered_words = masks
+        .iter()
+        .filter_map(|mask| mask_counts.masks.get(mask))
+        .fold(0usize, |total, &count| total.saturating_add(count));
+    let coverage = if mask_counts.total_words == 0 {
+        0.0
+    } else {
+        covered_words as f64 / mask_counts.total_words as f64
+    };
+
+    println!(
+        "{} masks covering {:.1}% of {} passwords",
+        masks.len(),
+        coverage * 100.0,
+        human_count(mask_counts.total_words)
+    );
+}
+
+fn run_masks(args: MasksArgs) {
+    let mut out: Box<dyn Write> = match &args.output_file {
+        Some(output_file) => match File::create(output_file) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(error) => {
+                eprintln!("cannot create {}: {}", output_file, error);
+                process::exit(1);
+            }
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    let default_filter = MaskFilter::default();
+    let options = ParseOptions {
+        maximum_size: args.space_limit,
+        filter: MaskFilter {
+            min_length: args.min_length.unwrap_or(default_filter.min_length),
+            max_length: args.max_length.unwrap_or(default_filter.max_length),
+            min_occurrence: args.min_occurrence,
+            max_mask_keyspace: args
+                .max_mask_keyspace
+                .unwrap_or(default_filter.max_mask_keyspace),
+            keep_over_budget: args.keep_over_budget,
+        },
+        config: seeded_mask_config(&args),
+        read: read_options(&args),
+        sort_key: args.sort.into(),
+        reverse: args.reverse,
+        strategy: args.optimize.into(),
+        top: args.top,
+        policy: args.require.clone().map(|required_classes| PolicyFilter {
+            required_classes: required_classes.0,
+            ..PolicyFilter::default()
+        }),
+        excluded_masks: excluded_masks(&args),
+        timings: args.timings,
+    };
+
+    if args.all {
+        let distribution = or_exit(analyze_files(&args.wordlists, &options));
+        let used_space = distribution
+            .masks
+            .iter()
+            .fold(0usize, |total, mask| total.saturating_add(mask.size));
+        let written = write_masks(
+            &mut out,
+            &args,
+            &distribution.masks,
+            &distribution.masks,
+            used_space,
+            distribution.total_words,
+        );
+        exit_on_write_error(written, &args);
+
+        if args.report_skipped {
+            write_skip_report(&distribution.skipped);
+        }
+        if !args.quiet {
+            let covered_words = distribution
+                .masks
+                .iter()
+                .fold(0usize, |total, mask| total.saturating_add(mask.count));
+            let coverage = if distribution.total_words == 0 {
+                0.0
+            } else {
+                covered_words as f64 / distribution.total_words as f64
+            };
+            eprintln!(
+                "{}",
+                summary_line(
+                    distribution.total_words,
+                    distribution.distinct_masks,
+                    distribution.masks.len(),
+                    distribution.total_keyspace,
+                    coverage
+                )
+            );
+        }
+        return;
+    }
+
+    if args.per_file {
+        let (file_results, combined) = or_exit(parse_files_separately(&args.wordlists, &options));
+        for (wordlist, result) in args.wordlists.iter().zip(&file_results) {
+            write_section(&mut out, &args, wordlist, result);
+        }
+        write_section(&mut out, &args, "combined", &combined);
+        write_summary(&args, &combined);
+        return;
+    }
+
+    if args.stream {
+        run_streamed(&mut out, &args, &options);
+        return;
+    }
+
+    #[cfg(feature = "serde")]
+    let accumulates = args.load_counts.is_some() || args.save_counts.is_some();
+    #[cfg(not(feature = "serde"))]
+    let accumulates = false;
+    let result = if accumulates || args.mem_report {
+        counted_result(&args, &options)
+    } else {
+        or_exit(parse_files(&args.wordlists, &options))
+    };
+    let printed_masks = if args.rejected {
+        &result.rejected
+    } else {
+        &result.masks
+    };
+
+    if let Some(sample) = args.sample {
+        eprintln!(
+            "counts are estimated from one line in {} of the wordlists",
+            sample
+        );
+    }
+
+    if result.capped_words > 0 {
+        eprintln!(
+            "{} words of masks beyond the first {} distinct ones were left uncounted",
+            result.capped_words, result.distinct_masks
+        );
+    }
+
+    let over_budget: Vec<&ComputedMask> = result
+        .rejected
+        .iter()
+        .filter(|mask| mask.over_budget)
+        .collect();
+    if !over_budget.is_empty() {
+        eprintln!(
+            "{} masks of {} words have a keyspace over the space limit",
+            over_budget.len(),
+            over_budget
+                .iter()
+                .fold(0usize, |total, mask| total.saturating_add(mask.count))
+        );
+    }
+
+    if result.masks.is_empty() && result.distinct_masks > 0 {
+        eprintln!(
+            "none of the {} distinct masks passed the filters and space limit",
+            result.distinct_masks
+        );
+    }
+
+    let written = if args.invert {
+        write_uncovered_words(&mut out, &args, &result.masks)
+    } else if let Some(top) = args.histogram {
+        write_histogram(
+            &mut out,
+            printed_masks,
+            top.unwrap_or(DEFAULT_HISTOGRAM_MASKS),
+            terminal_width(),
+            args.format,
+        )
+    } else {
+        write_masks(
+            &mut out,
+            &args,
+            printed_masks,
+            &result.masks,
+            result.used_space,
+            result.total_words,
+        )
+    };
+    exit_on_write_error(written, &args);
+
+    if args.coverage {
+        eprintln!(
+            "selected {} masks covering {:.1}% of {} passwords",
+            result.masks.len(),
+            result.coverage() * 100.0,
+            human_count(result.total_words)
+        );
+    }
+
+    if args.verify {
+        write_verification(&args, &result.masks);
+    }
+
+    if let Some(rate) = args.rate {
+        eprintln!(
+            "estimated run time: {} for a keyspace of {} at {} H/s",
+            human_duration(result.used_space as f64 / rate),
+            result.used_space,
+            rate
+        );
+    }
+
+    if let Some(timings) = result.timings {
+        eprintln!(
+            "generation: {:?}, sort: {:?}, selection: {:?}",
+            timings.generation_time, timings.sort_time, timings.selection_time
+        );
+    }
+
+    if args.report_skipped {
+        write_skip_report(&result.skipped);
+    }
+
+    write_summary(&args, &result);
+}
+
+/// Print the closing summary of `result` on stderr, unless --quiet.
+fn write_summary(args: &MasksArgs, result: &AnalysisResult) {
+    if !args.quiet {
+        eprintln!(
+            "{}",
+            summary_line(
+                result.total_words,
+                result.distinct_masks,
+                result.masks.len(),
+                result.used_space as u128,
+                result.coverage()
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod main_tests {
+    use clap::{CommandFactory, Parser};
+
+    use speedy_mask::ComputedMask;
+
+    use super::{
+        parse_multiplier, parse_on_unknown, parse_rate, parse_space_limit, parse_template,
+        summary_line, write_cumulative, write_histogram, write_text, Cli, Command, MaskFormat,
+        OnUnknown,
+    };
+
+    #[test]
+    fn cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn masks_without_subcommand() {
+        let cli = Cli::try_parse_from(["speedy_mask", "words.txt", "-l", "1M"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.masks.wordlists, ["words.txt"]);
+        assert_eq!(cli.masks.space_limit, 1_000_000);
+
+        let cli = Cli::try_parse_from(["speedy_mask", "stats", "--positions", "words.txt"]);
+        assert!(matches!(cli.unwrap().command, Some(Command::Stats(args)) if args.positions));
+        assert!(Cli::try_parse_from(["speedy_mask", "expand", "?u?d", "-n", "3"]).is_ok());
+        assert!(Cli::try_parse_from(["speedy_mask", "coverage", "words.txt"]).is_err());
+    }
+
+    #[test]
+    fn mask_line_template() {
+        let mask = ComputedMask {
+            mask: "ulld".to_string(),
+            size: 17576,
+            count: 42,
+            cost: 0.5,
+            entropy_bits: 14.1,
+            over_budget: false,
+        };
+
+        let template = parse_template("{mask}:{count} {{{size}}}").unwrap();
+        assert_eq!(
+            template.render(&mask, MaskFormat::Native),
+            "ulld:42 {17576}"
+        );
+        let template = parse_template("{mask},{cost},{entropy}").unwrap();
+        assert_eq!(
+            template.render(&mask, MaskFormat::Hashcat),
+            "?u?l?l?d,0.5,14.10"
+        );
+
+        let template = parse_template("{mask}").unwrap();
+        assert_eq!(template.render(&mask, MaskFormat::Both), "ulld\t?u?l?l?d");
+
+        assert!(parse_template("{mask}:{counts}").is_err());
+        assert!(parse_template("{mask").is_err());
+        assert!(parse_template("mask}").is_err());
+    }
+
+    #[test]
+    fn histogram_bars() {
+        let mask = |mask: &str, count| ComputedMask {
+            mask: mask.to_string(),
+            size: 1,
+            count,
+            cost: count as f64,
+            entropy_bits: 0.0,
+            over_budget: false,
+        };
+        let masks = [
+            mask("dd", 5),
+            mask("ullll", 20),
+            mask("lll", 10),
+            mask("s", 1),
+        ];
+
+        let mut out = Vec::new();
+        write_histogram(&mut out, &masks, 3, 19, MaskFormat::Native).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ullll ██████████ 20\nlll   █████ 10\ndd    ███ 5\n"
+        );
+    }
+
+    #[test]
+    fn pack_lines() {
+        let mask = |mask: &str, count| ComputedMask {
+            mask: mask.to_string(),
+            size: 1,
+            count,
+            cost: 1.0 / count as f64,
+            entropy_bits: 0.0,
+            over_budget: false,
+        };
+        let masks = [mask("dd", 5), mask("ullll", 20), mask("lll", 5)];
+
+        let mut out = Vec::new();
+        write_text(&mut out, &masks, MaskFormat::Pack, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "?u?l?l?l?l,20\n?d?d,5\n?l?l?l,5\n"
+        );
+    }
+
+    #[test]
+    fn cumulative_lines() {
+        let mask = |mask: &str, count| ComputedMask {
+            mask: mask.to_string(),
+            size: 1,
+            count,
+            cost: 1.0 / count as f64,
+            entropy_bits: 0.0,
+            over_budget: false,
+        };
+        let masks = [mask("ullll", 6), mask("dd", 1), mask("lll", 1)];
+
+        let mut out = Vec::new();
+        write_cumulative(&mut out, &masks, MaskFormat::Hashcat, 10).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "?u?l?l?l?l\t60.00%\n?d?d\t70.00%\n?l?l?l\t80.00%\n"
+        );
+    }
+
+    #[test]
+    fn space_limit_suffixes() {
+        assert_eq!(parse_space_limit("12345"), Ok(12345));
+        assert_eq!(parse_space_limit("500M"), Ok(500_000_000));
+        assert_eq!(parse_space_limit("1g"), Ok(1_000_000_000));
+        assert_eq!(parse_space_limit("2Ki"), Ok(2048));
+        assert_eq!(parse_space_limit("1gi"), Ok(1 << 30));
+        assert_eq!(parse_space_limit("18E"), Ok(18_000_000_000_000_000_000));
+
+        assert!(parse_space_limit("19E").is_err());
+        assert!(parse_space_limit("1X").is_err());
+        assert!(parse_space_limit("1i").is_err());
+        assert!(parse_space_limit("M").is_err());
+        assert!(parse_space_limit("1.5G").is_err());
+    }
+
+    #[test]
+    fn run_summary() {
+        assert_eq!(
+            summary_line(1_234_567, 420, 12, 5_000_000, 0.8752),
+            "1.2M passwords, 420 distinct masks, 12 selected with a keyspace of 5000000, \
+             covering 87.5%"
+        );
+    }
+
+    #[test]
+    fn unknown_character_actions() {
+        assert_eq!(parse_on_unknown("skip"), Ok(OnUnknown::Skip));
+        assert_eq!(parse_on_unknown("e=1000"), Ok(OnUnknown::Class('e', 1000)));
+
+        assert!(parse_on_unknown("e=0").is_err());
+        assert!(parse_on_unknown("s=10").is_err());
+        assert!(parse_on_unknown("ignore").is_err());
+    }
+
+    #[test]
+    fn positive_rates_only() {
+        assert_eq!(parse_rate("1.5e9"), Ok(1.5e9));
+        for rate in ["0", "-10", "inf", "NaN", "fast"] {
+            assert!(parse_rate(rate).is_err(), "{} accepted", rate);
+        }
+    }
+
+    #[test]
+    fn zero_multiplier_rejected() {
+        assert_eq!(parse_multiplier("5"), Ok(5));
+        assert!(parse_multiplier("0").is_err());
+        assert!(Cli::try_parse_from(["speedy_mask", "w.txt", "--special-keyspace", "0"]).is_err());
     }
 }
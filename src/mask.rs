@@ -0,0 +1,1836 @@
+//! Classification of words into masks, and the keyspace and cost of masks.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+pub(crate) const SPECIAL_CHARSET: &str = "! \"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+const WHITESPACE_CHARSET: &str = " \t";
+// character counts, the byte lengths would overstate non-ASCII charsets
+const SPECIAL_CHARSET_SIZE: usize = char_count(SPECIAL_CHARSET);
+const WHITESPACE_CHARSET_SIZE: usize = char_count(WHITESPACE_CHARSET);
+
+pub(crate) const fn char_count(charset: &str) -> usize {
+    let bytes = charset.as_bytes();
+    let mut count = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        // UTF-8 continuation bytes start with 0b10
+        if bytes[index] & 0xC0 != 0x80 {
+            count += 1;
+        }
+        index += 1;
+    }
+    count
+}
+
+/// Relative cost difference under which two masks count as tied and the shorter one goes first.
+pub const DEFAULT_COST_EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MaskError {
+    InvalidCharacter(char),
+    /// The word to classify is empty, e.g. a blank line.
+    EmptyWord,
+    /// A mask contains a letter that is not a mask class.
+    UnknownClass(char),
+}
+
+impl Display for MaskError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaskError::InvalidCharacter(bad_char) => {
+                write!(f, "invalid character '{}'", bad_char)
+            }
+            MaskError::EmptyWord => write!(f, "empty word"),
+            MaskError::UnknownClass(letter) => write!(f, "unknown mask class '{}'", letter),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ComputedMask {
+    pub mask: String,
+    pub size: usize,
+    pub count: usize,
+    pub cost: f64,
+    /// Base 2 logarithm of the keyspace.
+    pub entropy_bits: f64,
+    /// Keyspace over the budget, kept with [`MaskFilter::keep_over_budget`] with a `size` of
+    /// `usize::MAX` and a zero cost. Such masks are sorted last and never selected.
+    ///
+    /// [`MaskFilter::keep_over_budget`]: crate::MaskFilter::keep_over_budget
+    pub over_budget: bool,
+}
+
+impl ComputedMask {
+    /// Mask in hashcat token form, e.g. `?u?l?l?l?l` for `ullll`.
+    pub fn to_hashcat_mask(&self) -> String {
+        to_hashcat_mask(&self.mask)
+    }
+}
+
+impl Display for ComputedMask {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mask)
+    }
+}
+
+/// Built-in class of a mask position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MaskClass {
+    Lower,
+    Upper,
+    Digit,
+    Special,
+    /// Non-ASCII letter of the Unicode mode.
+    Letter,
+    /// Non-ASCII numeric character of the Unicode mode.
+    Number,
+    /// Space or tab of the whitespace mode.
+    Whitespace,
+    /// Digit of the run starting a word, with split digits.
+    LeadingDigit,
+    /// Digit of the run ending a word, with split digits.
+    TrailingDigit,
+    /// ASCII letter of either case, with [`LetterMergeMode::AnyLetter`].
+    AnyLetter,
+}
+
+impl MaskClass {
+    /// Class of a mask letter, `None` if the letter is not a built-in class.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'l' => Some(MaskClass::Lower),
+            'u' => Some(MaskClass::Upper),
+            'd' => Some(MaskClass::Digit),
+            's' => Some(MaskClass::Special),
+            'L' => Some(MaskClass::Letter),
+            'N' => Some(MaskClass::Number),
+            'w' => Some(MaskClass::Whitespace),
+            'p' => Some(MaskClass::LeadingDigit),
+            't' => Some(MaskClass::TrailingDigit),
+            'a' => Some(MaskClass::AnyLetter),
+            _ => None,
+        }
+    }
+
+    /// Letter standing for the class in masks.
+    pub fn letter(self) -> char {
+        match self {
+            MaskClass::Lower => 'l',
+            MaskClass::Upper => 'u',
+            MaskClass::Digit => 'd',
+            MaskClass::Special => 's',
+            MaskClass::Letter => 'L',
+            MaskClass::Number => 'N',
+            MaskClass::Whitespace => 'w',
+            MaskClass::LeadingDigit => 'p',
+            MaskClass::TrailingDigit => 't',
+            MaskClass::AnyLetter => 'a',
+        }
+    }
+}
+
+/// Split a native mask into its classes, failing on the first letter that is not a built-in
+/// class.
+pub fn parse_mask(mask: &str) -> Result<Vec<MaskClass>, MaskError> {
+    mask.chars()
+        .map(|letter| MaskClass::from_letter(letter).ok_or(MaskError::UnknownClass(letter)))
+        .collect()
+}
+
+/// Convert a native `l/u/d/s` mask into hashcat tokens. An empty mask gives an empty string.
+///
+/// The Unicode, whitespace and split digit classes have no hashcat counterpart and come out as
+/// `?L`, `?N`, `?w`, `?p` and `?t`. The any letter class comes out as `?a`, which hashcat reads
+/// as every printable character, a superset of its letters.
+pub fn to_hashcat_mask(mask: &str) -> String {
+    let mut hashcat_mask = String::with_capacity(mask.len() * 2);
+
+    for class in mask.chars() {
+        hashcat_mask.push('?');
+        hashcat_mask.push(class);
+    }
+
+    hashcat_mask
+}
+
+/// Line of a hashcat `.hcmask` file for `mask`, positions seeing fewer characters than their
+/// class in `position_chars` getting custom charsets, e.g. `Pp,?1?l?l?l` for `ulll`.
+///
+/// Hashcat takes at most four custom charsets, positions of the same class share one when more
+/// would be needed and the remaining ones keep their class token. Positions that saw non-ASCII
+/// characters or backslashes, which hcmask files cannot escape, keep their class token too.
+pub fn to_hcmask_line(
+    mask: &str,
+    position_chars: &[BTreeSet<char>],
+    charset_sizes: &CharsetSizes,
+) -> String {
+    const MAX_CUSTOM_CHARSETS: usize = 4;
+
+    let classes: Vec<char> = mask.chars().collect();
+    let mut custom_charsets: Vec<Option<BTreeSet<char>>> = classes
+        .iter()
+        .enumerate()
+        .map(|(position, &class)| {
+            let chars = position_chars.get(position)?;
+            let is_reducible = !chars.is_empty()
+                && chars.iter().all(|&char| char.is_ascii() && char != '\\')
+                && charset_sizes
+                    .multiplier(class)
+                    .is_some_and(|size| chars.len() < size);
+            is_reducible.then(|| chars.clone())
+        })
+        .collect();
+
+    let mut charsets = distinct_charsets(&custom_charsets);
+    if charsets.len() > MAX_CUSTOM_CHARSETS {
+        // one charset per class, merging the characters of all its positions
+        let mut class_charsets: HashMap<char, BTreeSet<char>> = HashMap::new();
+        for (&class, charset) in classes.iter().zip(&custom_charsets) {
+            if let Some(charset) = charset {
+                class_charsets
+                    .entry(class)
+                    .or_default()
+                    .extend(charset.iter().copied());
+            }
+        }
+        for (class, charset) in classes.iter().zip(&mut custom_charsets) {
+            if charset.is_some() {
+                *charset = Some(class_charsets[class].clone());
+            }
+        }
+        charsets = distinct_charsets(&custom_charsets);
+        charsets.truncate(MAX_CUSTOM_CHARSETS);
+    }
+
+    let mut line = String::new();
+    for charset in &charsets {
+        for &char in charset {
+            match char {
+                ',' => line.push_str("\\,"),
+                '?' => line.push_str("??"),
+                _ => line.push(char),
+            }
+        }
+        line.push(',');
+    }
+    for (&class, charset) in classes.iter().zip(&custom_charsets) {
+        match charset
+            .as_ref()
+            .and_then(|charset| charsets.iter().position(|other| other == charset))
+        {
+            Some(index) => line.push_str(&format!("?{}", index + 1)),
+            None => line.push_str(&to_hashcat_mask(&class.to_string())),
+        }
+    }
+
+    line
+}
+
+/// Distinct charsets of `custom_charsets`, in order of first appearance.
+fn distinct_charsets(custom_charsets: &[Option<BTreeSet<char>>]) -> Vec<BTreeSet<char>> {
+    let mut charsets: Vec<BTreeSet<char>> = Vec::new();
+    for charset in custom_charsets.iter().flatten() {
+        if !charsets.contains(charset) {
+            charsets.push(charset.clone());
+        }
+    }
+    charsets
+}
+
+/// Parse a hashcat mask made of `?l`, `?u`, `?d` and `?s` tokens back into native form.
+///
+/// The `?L`, `?N` and `?w` tokens of [`to_hashcat_mask`] are accepted as well.
+pub fn parse_hashcat_mask(hashcat_mask: &str) -> Result<String, MaskError> {
+    parse_hashcat_tokens(hashcat_mask, |letter| {
+        MaskClass::from_letter(letter).is_some()
+    })
+}
+
+/// Same as [`parse_hashcat_mask`], also accepting the letters of the custom classes and of the
+/// [`OnUnknown::Class`] of `config`, so masks using them round-trip through [`to_hashcat_mask`].
+pub fn parse_hashcat_mask_with(
+    hashcat_mask: &str,
+    config: &MaskConfig,
+) -> Result<String, MaskError> {
+    parse_hashcat_tokens(hashcat_mask, |letter| {
+        config.charset_sizes.multiplier(letter).is_some()
+    })
+}
+
+fn parse_hashcat_tokens(
+    hashcat_mask: &str,
+    is_class: impl Fn(char) -> bool,
+) -> Result<String, MaskError> {
+    let mut mask = String::with_capacity(hashcat_mask.len() / 2);
+    let mut chars = hashcat_mask.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '?' {
+            return Err(MaskError::InvalidCharacter(char));
+        }
+
+        match chars.next() {
+            Some(letter) if is_class(letter) => mask.push(letter),
+            Some(letter) => return Err(MaskError::UnknownClass(letter)),
+            None => return Err(MaskError::InvalidCharacter('?')),
+        }
+    }
+
+    Ok(mask)
+}
+
+/// Canonical native form of a mask given either natively or as hashcat tokens, as `config`
+/// would have generated it, e.g. `llld` for `?u?l?l?d` when folding case.
+///
+/// Under `config`, letters are merged following its [`LetterMergeMode`], spaces and tabs are
+/// only `w` in the whitespace mode, and digits are only split into `p`/`t` runs with split
+/// digits. Letters of its custom classes are accepted as they are, any other letter fails with
+/// [`MaskError::UnknownClass`].
+pub fn normalize_mask(mask: &str, config: &MaskConfig) -> Result<String, MaskError> {
+    let classes: Vec<char> = if mask.starts_with('?') {
+        let tokens: Vec<char> = mask.chars().collect();
+        tokens
+            .chunks(2)
+            .map(|token| match token {
+                ['?', letter] => Ok(*letter),
+                [char, ..] => Err(MaskError::InvalidCharacter(*char)),
+                [] => unreachable!("chunks are never empty"),
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        mask.chars().collect()
+    };
+
+    let mut normalized = String::with_capacity(classes.len());
+    for class in classes {
+        if config.charset_sizes.multiplier(class).is_none() {
+            return Err(MaskError::UnknownClass(class));
+        }
+        normalized.push(match class {
+            'l' | 'u' if config.letter_merge_mode == LetterMergeMode::AnyLetter => 'a',
+            'u' if config.letter_merge_mode == LetterMergeMode::MergedLetter => 'l',
+            'w' if !config.whitespace => 's',
+            'p' | 't' => 'd',
+            class => class,
+        });
+    }
+    if config.split_digits {
+        split_digit_runs(&mut normalized);
+    }
+
+    Ok(normalized)
+}
+
+/// Number of candidates each mask class stands for when computing keyspaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharsetSizes {
+    pub lower: usize,
+    pub upper: usize,
+    pub digit: usize,
+    pub special: usize,
+    /// Non-ASCII letters of the Unicode mode, class `L`.
+    ///
+    /// There is no fixed alphabet behind it, the default of 66 (both cases of the 33 Cyrillic
+    /// letters) assumes a wordlist drawing its accented or foreign letters from a single script.
+    pub letter: usize,
+    /// Non-ASCII numeric characters of the Unicode mode, class `N`, assumed to be a 10 digits
+    /// system by default.
+    pub number: usize,
+    /// Spaces and tabs of the whitespace mode, class `w`, 2 by default.
+    pub whitespace: usize,
+    /// Digits starting a word with split digits, class `p`, 10 by default.
+    pub leading_digit: usize,
+    /// Digits ending a word with split digits, class `t`, 10 by default.
+    ///
+    /// Lowering it, e.g. to 4 for the `19`/`20` prefix of years, makes keyspaces estimates of
+    /// the likely candidates rather than counts of all of them.
+    pub trailing_digit: usize,
+    /// ASCII letters of both cases with [`LetterMergeMode::AnyLetter`], class `a`, 52 by default.
+    pub any_letter: usize,
+    /// Multipliers of the [`CustomClass`] letters.
+    pub custom: BTreeMap<char, usize>,
+}
+
+impl CharsetSizes {
+    /// Multiplier of a mask class letter, `None` if the letter is not a class.
+    pub fn multiplier(&self, class: char) -> Option<usize> {
+        match class {
+            'l' => Some(self.lower),
+            'u' => Some(self.upper),
+            'd' => Some(self.digit),
+            's' => Some(self.special),
+            'L' => Some(self.letter),
+            'N' => Some(self.number),
+            'w' => Some(self.whitespace),
+            'p' => Some(self.leading_digit),
+            't' => Some(self.trailing_digit),
+            'a' => Some(self.any_letter),
+            _ => self.custom.get(&class).copied(),
+        }
+    }
+}
+
+impl Default for CharsetSizes {
+    fn default() -> Self {
+        Self {
+            lower: 26,
+            upper: 26,
+            digit: 10,
+            special: SPECIAL_CHARSET_SIZE,
+            letter: 66,
+            number: 10,
+            whitespace: WHITESPACE_CHARSET_SIZE,
+            leading_digit: 10,
+            trailing_digit: 10,
+            any_letter: 52,
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+/// Letters of the built-in mask classes.
+pub const BUILTIN_CLASSES: &str = "ludsLNwpta";
+
+/// User defined mask class, e.g. vowels, consulted before the built-in classes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomClass {
+    /// ASCII letter standing for the class in masks.
+    pub letter: char,
+    pub charset: String,
+    /// Keyspace multiplier of the class.
+    pub size: usize,
+}
+
+impl CustomClass {
+    /// Class matching the characters of `charset`, sized after its length.
+    pub fn new(letter: char, charset: &str) -> Self {
+        Self {
+            letter,
+            charset: charset.to_string(),
+            size: charset.chars().count(),
+        }
+    }
+}
+
+/// Predefined special charsets, to pass to [`MaskConfigBuilder::special_charset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialCharset {
+    /// Hashcat `?s` set: the ASCII punctuation and the space, the default one.
+    HashcatS,
+    /// ASCII punctuation without the space.
+    AsciiSpecial,
+    /// Symbols of the digit keys of a US keyboard, `!@#$%^&*()`.
+    TopRow,
+}
+
+impl SpecialCharset {
+    pub fn charset(self) -> &'static str {
+        match self {
+            SpecialCharset::HashcatS => SPECIAL_CHARSET,
+            SpecialCharset::AsciiSpecial => "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~",
+            SpecialCharset::TopRow => "!@#$%^&*()",
+        }
+    }
+}
+
+/// What happens to characters outside every class, see [`MaskConfigBuilder::on_unknown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnUnknown {
+    /// Fail with [`MaskError::InvalidCharacter`], the word being counted as skipped.
+    #[default]
+    Error,
+    /// Fail like [`OnUnknown::Error`], the word being left out of the counts altogether.
+    Skip,
+    /// Classify them with this letter and keyspace multiplier, e.g. `('e', 1000)` for emoji.
+    Class(char, usize),
+}
+
+/// How ASCII letters are classified, see [`MaskConfigBuilder::letter_merge_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LetterMergeMode {
+    /// Lowercase letters as `l` and uppercase ones as `u`.
+    #[default]
+    Separate,
+    /// Both cases as `l`, the same as [`MaskConfigBuilder::fold_case`].
+    ///
+    /// The multiplier stays the 26 of `l`, so keyspaces only count the lowercase candidates of
+    /// words whose case is expected to be restored by rules.
+    MergedLetter,
+    /// Both cases as `a`, whose multiplier covers the 52 letters.
+    ///
+    /// Unlike case folding, keyspaces count every candidate of mixed case positions.
+    AnyLetter,
+}
+
+/// Character classification settings used when generating masks.
+#[derive(Debug, Clone)]
+pub struct MaskConfig {
+    special_charset: String,
+    charset_sizes: CharsetSizes,
+    special_keyspace: Option<usize>,
+    letter_merge_mode: LetterMergeMode,
+    unicode: bool,
+    whitespace: bool,
+    split_digits: bool,
+    cost_epsilon: f64,
+    cost_metric: CostMetric,
+    custom_classes: Vec<CustomClass>,
+    on_unknown: OnUnknown,
+    /// Class letter of each ASCII character, derived from the other settings.
+    pub(crate) ascii_classes: [Option<u8>; 128],
+}
+
+impl MaskConfig {
+    pub fn builder() -> MaskConfigBuilder {
+        MaskConfigBuilder::new()
+    }
+
+    pub fn special_charset(&self) -> &str {
+        &self.special_charset
+    }
+
+    pub fn charset_sizes(&self) -> &CharsetSizes {
+        &self.charset_sizes
+    }
+
+    /// Multiplier of `s` set by [`MaskConfigBuilder::special_keyspace`], if any.
+    pub fn special_keyspace(&self) -> Option<usize> {
+        self.special_keyspace
+    }
+
+    pub fn fold_case(&self) -> bool {
+        self.letter_merge_mode == LetterMergeMode::MergedLetter
+    }
+
+    pub fn letter_merge_mode(&self) -> LetterMergeMode {
+        self.letter_merge_mode
+    }
+
+    pub fn unicode(&self) -> bool {
+        self.unicode
+    }
+
+    pub fn whitespace(&self) -> bool {
+        self.whitespace
+    }
+
+    pub fn split_digits(&self) -> bool {
+        self.split_digits
+    }
+
+    pub fn cost_epsilon(&self) -> f64 {
+        self.cost_epsilon
+    }
+
+    pub fn cost_metric(&self) -> CostMetric {
+        self.cost_metric
+    }
+
+    pub fn custom_classes(&self) -> &[CustomClass] {
+        &self.custom_classes
+    }
+
+    pub fn on_unknown(&self) -> OnUnknown {
+        self.on_unknown
+    }
+}
+
+impl Default for MaskConfig {
+    fn default() -> Self {
+        MaskConfigBuilder::new().build()
+    }
+}
+
+/// Builder of [`MaskConfig`], unset options keep their default.
+#[derive(Debug, Clone, Default)]
+pub struct MaskConfigBuilder {
+    special_charset: Option<String>,
+    charset_sizes: Option<CharsetSizes>,
+    special_keyspace: Option<usize>,
+    letter_merge_mode: LetterMergeMode,
+    unicode: bool,
+    whitespace: bool,
+    split_digits: bool,
+    cost_epsilon: Option<f64>,
+    cost_metric: CostMetric,
+    custom_classes: Vec<CustomClass>,
+    on_unknown: OnUnknown,
+}
+
+impl MaskConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify only the characters of `special_charset` as `s`; any other
+    /// non-alphanumeric character makes the word invalid.
+    pub fn special_charset(mut self, special_charset: &str) -> Self {
+        self.special_charset = Some(special_charset.to_string());
+        self
+    }
+
+    /// Replace the keyspace model, classification is left unchanged.
+    ///
+    /// Without it, the special multiplier follows the special charset length.
+    pub fn charset_sizes(mut self, charset_sizes: CharsetSizes) -> Self {
+        self.charset_sizes = Some(charset_sizes);
+        self
+    }
+
+    /// Use `special_keyspace` as the multiplier of `s`, e.g. the 5 symbols a target policy
+    /// allows, whatever the special charset classifying characters and the [`charset_sizes`].
+    ///
+    /// # Panics
+    ///
+    /// If `special_keyspace` is zero.
+    ///
+    /// [`charset_sizes`]: MaskConfigBuilder::charset_sizes
+    pub fn special_keyspace(mut self, special_keyspace: usize) -> Self {
+        assert!(special_keyspace > 0, "the special keyspace cannot be zero");
+        self.special_keyspace = Some(special_keyspace);
+        self
+    }
+
+    /// Classify uppercase letters as `l`, merging both cases into a single 26 letters class.
+    pub fn fold_case(mut self, fold_case: bool) -> Self {
+        self.letter_merge_mode = if fold_case {
+            LetterMergeMode::MergedLetter
+        } else {
+            LetterMergeMode::Separate
+        };
+        self
+    }
+
+    /// Classify ASCII letters following `letter_merge_mode`, replacing [`fold_case`].
+    ///
+    /// [`fold_case`]: MaskConfigBuilder::fold_case
+    pub fn letter_merge_mode(mut self, letter_merge_mode: LetterMergeMode) -> Self {
+        self.letter_merge_mode = letter_merge_mode;
+        self
+    }
+
+    /// Classify non-ASCII letters as `L` and non-ASCII numeric characters as `N` instead of
+    /// dropping their words.
+    ///
+    /// Keyspaces of such masks are approximate, see [`CharsetSizes::letter`].
+    pub fn unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    /// Classify spaces and tabs as `w` instead of `s` and invalid characters respectively.
+    ///
+    /// Spaces no longer count in the special multiplier, unless [`charset_sizes`] are given.
+    ///
+    /// [`charset_sizes`]: MaskConfigBuilder::charset_sizes
+    pub fn whitespace(mut self, whitespace: bool) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Classify the digit run starting a word as `p` and the one ending it as `t`, the other
+    /// digits and the words made of digits only staying `d`.
+    ///
+    /// Leading digits tend to be dates or PINs while trailing ones are mostly years and short
+    /// counters, splitting them gives masks modelling each apart. Both classes keep the 10
+    /// digits as multiplier, see [`CharsetSizes::trailing_digit`] for smaller ones.
+    pub fn split_digits(mut self, split_digits: bool) -> Self {
+        self.split_digits = split_digits;
+        self
+    }
+
+    /// Relative cost difference under which the cost order puts shorter masks first,
+    /// [`DEFAULT_COST_EPSILON`] by default.
+    pub fn cost_epsilon(mut self, cost_epsilon: f64) -> Self {
+        self.cost_epsilon = Some(cost_epsilon);
+        self
+    }
+
+    /// Cost of the masks, [`CostMetric::CountPerKeyspace`] by default.
+    pub fn cost_metric(mut self, cost_metric: CostMetric) -> Self {
+        self.cost_metric = cost_metric;
+        self
+    }
+
+    /// Add a class checked before the built-in ones, in the order classes were added.
+    ///
+    /// Its size is registered as the multiplier of its letter, overriding [`charset_sizes`].
+    ///
+    /// # Panics
+    ///
+    /// If the letter is not ASCII alphabetic or is the letter of a built-in class.
+    ///
+    /// [`charset_sizes`]: MaskConfigBuilder::charset_sizes
+    pub fn custom_class(mut self, custom_class: CustomClass) -> Self {
+        assert!(
+            custom_class.letter.is_ascii_alphabetic()
+                && !BUILTIN_CLASSES.contains(custom_class.letter),
+            "invalid custom class letter '{}'",
+            custom_class.letter
+        );
+        self.custom_classes.push(custom_class);
+        self
+    }
+
+    /// Handle characters matching no class following `on_unknown`, instead of failing.
+    ///
+    /// A catch-all class makes words with emoji or other Unicode symbols count, its multiplier
+    /// standing for all of them; hashcat has no such charset, keyspaces are estimates.
+    ///
+    /// # Panics
+    ///
+    /// If the class letter is not ASCII alphabetic or is the letter of a built-in class, or if
+    /// its multiplier is zero.
+    pub fn on_unknown(mut self, on_unknown: OnUnknown) -> Self {
+        if let OnUnknown::Class(letter, size) = on_unknown {
+            assert!(
+                letter.is_ascii_alphabetic() && !BUILTIN_CLASSES.contains(letter),
+                "invalid unknown class letter '{}'",
+                letter
+            );
+            assert!(size > 0, "the unknown class multiplier cannot be zero");
+        }
+        self.on_unknown = on_unknown;
+        self
+    }
+
+    pub fn build(self) -> MaskConfig {
+        let special_charset = self
+            .special_charset
+            .unwrap_or_else(|| SPECIAL_CHARSET.to_string());
+        let mut charset_sizes = self.charset_sizes.unwrap_or_else(|| CharsetSizes {
+            special: if self.whitespace {
+                special_charset
+                    .chars()
+                    .filter(|&char| !WHITESPACE_CHARSET.contains(char))
+                    .count()
+            } else {
+                special_charset.chars().count()
+            },
+            ..CharsetSizes::default()
+        });
+        if let Some(special_keyspace) = self.special_keyspace {
+            charset_sizes.special = special_keyspace;
+        }
+        for custom_class in &self.custom_classes {
+            charset_sizes
+                .custom
+                .insert(custom_class.letter, custom_class.size);
+        }
+        if let OnUnknown::Class(letter, size) = self.on_unknown {
+            charset_sizes.custom.insert(letter, size);
+        }
+
+        let mut config = MaskConfig {
+            special_charset,
+            charset_sizes,
+            special_keyspace: self.special_keyspace,
+            letter_merge_mode: self.letter_merge_mode,
+            unicode: self.unicode,
+            whitespace: self.whitespace,
+            split_digits: self.split_digits,
+            cost_epsilon: self.cost_epsilon.unwrap_or(DEFAULT_COST_EPSILON),
+            cost_metric: self.cost_metric,
+            custom_classes: self.custom_classes,
+            on_unknown: self.on_unknown,
+            ascii_classes: [None; 128],
+        };
+        for byte in 0..128u8 {
+            config.ascii_classes[byte as usize] =
+                classify_char(byte as char, &config).map(|letter| letter as u8);
+        }
+        config
+    }
+}
+
+/// Classify each character of `word`, failing with [`MaskError::EmptyWord`] on an empty word.
+pub fn generate_mask(word: &str, config: &MaskConfig) -> Result<String, MaskError> {
+    let mut mask = String::with_capacity(word.len());
+    generate_mask_into(word, config, &mut mask)?;
+    Ok(mask)
+}
+
+/// Same as [`generate_mask`], writing the mask into `mask` so its allocation can be reused.
+///
+/// `mask` is cleared first, and holds a partial mask when an error is returned.
+pub fn generate_mask_into(
+    word: &str,
+    config: &MaskConfig,
+    mask: &mut String,
+) -> Result<(), MaskError> {
+    mask.clear();
+    if word.is_empty() {
+        return Err(MaskError::EmptyWord);
+    }
+
+    for char in word.chars() {
+        let class = if char.is_ascii() {
+            config.ascii_classes[char as usize].map(char::from)
+        } else {
+            classify_char(char, config)
+        };
+        match class {
+            Some(class) => mask.push(class),
+            None => return Err(MaskError::InvalidCharacter(char)),
+        }
+    }
+
+    if config.split_digits {
+        split_digit_runs(mask);
+    }
+    Ok(())
+}
+
+/// Turn the leading `d` run of a mask into `p` and the trailing one into `t`, leaving masks made
+/// of digits only untouched.
+fn split_digit_runs(mask: &mut String) {
+    let is_digit = |class: char| class == 'd';
+    let (start, end) = match (
+        mask.find(|class| !is_digit(class)),
+        mask.rfind(|class| !is_digit(class)),
+    ) {
+        (Some(start), Some(end)) => (start, end + 1),
+        _ => return,
+    };
+
+    if end < mask.len() {
+        mask.replace_range(end.., &"t".repeat(mask.len() - end));
+    }
+    if start > 0 {
+        mask.replace_range(..start, &"p".repeat(start));
+    }
+}
+
+/// Mask of `word` along with the characters seen at each of its positions, to be merged across
+/// words of the same mask into reduced per-position charsets.
+pub fn analyze_word(
+    word: &str,
+    config: &MaskConfig,
+) -> Result<(String, Vec<HashSet<char>>), MaskError> {
+    let mask = generate_mask(word, config)?;
+    let position_chars = word.chars().map(|char| HashSet::from([char])).collect();
+    Ok((mask, position_chars))
+}
+
+/// Class letter of `char`, looking up every setting of `config`; ASCII characters are looked up
+/// in the table built from it instead.
+pub(crate) fn classify_char(char: char, config: &MaskConfig) -> Option<char> {
+    if let Some(custom_class) = config
+        .custom_classes
+        .iter()
+        .find(|custom_class| custom_class.charset.contains(char))
+    {
+        Some(custom_class.letter)
+    } else if char.is_ascii_alphabetic() {
+        Some(match config.letter_merge_mode {
+            LetterMergeMode::AnyLetter => 'a',
+            _ if char.is_ascii_lowercase() => 'l',
+            LetterMergeMode::MergedLetter => 'l',
+            LetterMergeMode::Separate => 'u',
+        })
+    } else if char.is_ascii_digit() {
+        Some('d')
+    } else if config.whitespace && WHITESPACE_CHARSET.contains(char) {
+        Some('w')
+    } else if config.special_charset.contains(char) {
+        Some('s')
+    } else if config.unicode && char.is_alphabetic() {
+        Some('L')
+    } else if config.unicode && char.is_numeric() {
+        Some('N')
+    } else if let OnUnknown::Class(letter, _) = config.on_unknown {
+        Some(letter)
+    } else {
+        None
+    }
+}
+
+/// Keyspace of a mask, `None` once it exceeds `maximum_size`.
+///
+/// Fails with [`MaskError::UnknownClass`] on a letter without multiplier.
+pub fn compute_mask_size(
+    mask: &str,
+    maximum_size: usize,
+    charset_sizes: &CharsetSizes,
+) -> Result<Option<usize>, MaskError> {
+    let mut result = 1;
+    let mut fits = true;
+
+    // checking every letter, so an unknown class is reported even past the budget
+    for char in mask.chars() {
+        let multiplier = class_multiplier(char, charset_sizes)?;
+
+        // a zero multiplier, only found in hand-made sizes, empties the keyspace
+        if fits
+            && matches!(maximum_size.checked_div(multiplier), Some(quotient) if quotient < result)
+        {
+            fits = false;
+        }
+
+        if fits {
+            result *= multiplier;
+        }
+    }
+
+    Ok(fits.then_some(result))
+}
+
+/// Whether the keyspace of a mask is at most `maximum_size`, comparing summed logarithms so
+/// masks of any length are handled without overflow.
+///
+/// Only keyspaces too close to the budget for the logarithms to tell are computed exactly, use
+/// [`compute_mask_size`] to report the size itself.
+pub fn mask_fits(
+    mask: &str,
+    maximum_size: usize,
+    charset_sizes: &CharsetSizes,
+) -> Result<bool, MaskError> {
+    // far above the rounding errors of summing a few hundred logarithms
+    const LOG_MARGIN: f64 = 1e-9;
+
+    let mut log_size = 0.0;
+    for char in mask.chars() {
+        log_size += (class_multiplier(char, charset_sizes)? as f64).log2();
+    }
+
+    let log_maximum_size = (maximum_size as f64).log2();
+    if log_size < log_maximum_size - LOG_MARGIN {
+        Ok(true)
+    } else if log_size > log_maximum_size + LOG_MARGIN {
+        Ok(false)
+    } else {
+        Ok(compute_mask_keyspace(mask, charset_sizes)? <= maximum_size as u128)
+    }
+}
+
+fn class_multiplier(class: char, charset_sizes: &CharsetSizes) -> Result<usize, MaskError> {
+    charset_sizes
+        .multiplier(class)
+        .ok_or(MaskError::UnknownClass(class))
+}
+
+/// Exact keyspace of a mask, without any budget.
+///
+/// Saturates at `u128::MAX`, which is only reached by masks longer than about 19 characters.
+pub fn compute_mask_keyspace(mask: &str, charset_sizes: &CharsetSizes) -> Result<u128, MaskError> {
+    let mut result: u128 = 1;
+
+    for char in mask.chars() {
+        let multiplier = class_multiplier(char, charset_sizes)?;
+        result = result.saturating_mul(multiplier as u128);
+    }
+
+    Ok(result)
+}
+
+/// Base 2 logarithm of the mask keyspace, summed per class so it never overflows.
+pub fn compute_mask_entropy(mask: &str, charset_sizes: &CharsetSizes) -> Result<f64, MaskError> {
+    mask.chars()
+        .map(|char| {
+            class_multiplier(char, charset_sizes).map(|multiplier| (multiplier as f64).log2())
+        })
+        .sum()
+}
+
+/// Collapse runs of a class into its letter followed by the run length, e.g. `l8d2` for
+/// `lllllllldd`.
+pub fn simplify_mask(mask: &str) -> String {
+    let mut simple_mask = String::new();
+    let mut chars = mask.chars().peekable();
+
+    while let Some(class) = chars.next() {
+        let mut length = 1;
+        while chars.next_if_eq(&class).is_some() {
+            length += 1;
+        }
+        simple_mask.push(class);
+        simple_mask.push_str(&length.to_string());
+    }
+
+    simple_mask
+}
+
+/// Coarser mask of `word` grouping runs of a class, e.g. `l8d2` for `password12`.
+///
+/// Characters are classified like [`generate_mask`] does.
+pub fn generate_simple_mask(word: &str, config: &MaskConfig) -> Result<String, MaskError> {
+    generate_mask(word, config).map(|mask| simplify_mask(&mask))
+}
+
+/// Exact keyspace of a simple mask, saturating like [`compute_mask_keyspace`].
+///
+/// Fails with [`MaskError::InvalidCharacter`] on a class letter missing its run length.
+pub fn compute_simple_mask_keyspace(
+    simple_mask: &str,
+    charset_sizes: &CharsetSizes,
+) -> Result<u128, MaskError> {
+    let mut result: u128 = 1;
+    let mut chars = simple_mask.chars().peekable();
+
+    while let Some(class) = chars.next() {
+        if class.is_ascii_digit() {
+            return Err(MaskError::InvalidCharacter(class));
+        }
+        let multiplier = class_multiplier(class, charset_sizes)? as u128;
+
+        let mut length: Option<u32> = None;
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            let digit = digit.to_digit(10).unwrap();
+            length = Some(length.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        }
+        let length = length.ok_or(MaskError::InvalidCharacter(class))?;
+
+        result = result.saturating_mul(multiplier.checked_pow(length).unwrap_or(u128::MAX));
+    }
+
+    Ok(result)
+}
+
+/// Occurrences per candidate of the mask keyspace, the default [`CostMetric`].
+pub fn compute_mask_cost(mask_size: usize, occurrences_count: usize) -> f64 {
+    (occurrences_count as f64) / (mask_size as f64)
+}
+
+/// How the cost ordering masks is computed from their keyspace and occurrences.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CostMetric {
+    /// Occurrences per candidate, see [`compute_mask_cost`].
+    #[default]
+    CountPerKeyspace,
+    /// Occurrences per bit of keyspace, much less biased towards short masks. Keyspaces below
+    /// 2 count as a single bit.
+    CountPerLog2Keyspace,
+    /// Base 2 logarithm of the occurrences per candidate, `log2(count) - entropy_bits`. Orders
+    /// masks like [`CostMetric::CountPerKeyspace`], but stays finite for masks of any length so
+    /// those whose keyspace overflows `usize` are ranked last instead of being dropped.
+    Log2CountPerKeyspace,
+    /// Cost computed from the keyspace and the occurrences, in this order.
+    Custom(fn(usize, usize) -> f64),
+}
+
+impl CostMetric {
+    pub fn cost(self, mask_size: usize, occurrences_count: usize) -> f64 {
+        match self {
+            CostMetric::CountPerKeyspace => compute_mask_cost(mask_size, occurrences_count),
+            CostMetric::CountPerLog2Keyspace => {
+                occurrences_count as f64 / (mask_size as f64).log2().max(1.0)
+            }
+            CostMetric::Log2CountPerKeyspace => {
+                log2_mask_cost((mask_size as f64).log2(), occurrences_count)
+            }
+            CostMetric::Custom(cost) => cost(mask_size, occurrences_count),
+        }
+    }
+}
+
+pub(crate) fn log2_mask_cost(entropy_bits: f64, occurrences_count: usize) -> f64 {
+    (occurrences_count as f64).log2() - entropy_bits
+}
+
+/// Up to `count` distinct random words matching `mask`, reproducible for a given `seed`.
+///
+/// Fewer words are returned when the mask keyspace is smaller than `count`, or when drawing
+/// keeps repeating words beyond a bounded number of attempts.
+pub fn sample_words(
+    mask: &str,
+    count: usize,
+    config: &MaskConfig,
+    seed: u64,
+) -> Result<Vec<String>, MaskError> {
+    let charsets = mask
+        .chars()
+        .map(|class| class_charset(class, config))
+        .collect::<Result<Vec<Vec<char>>, MaskError>>()?;
+    let keyspace = charsets.iter().fold(1u128, |keyspace, charset| {
+        keyspace.saturating_mul(charset.len() as u128)
+    });
+    let target = (count as u128).min(keyspace) as usize;
+    let mut rng = SampleRng(seed);
+    let mut words = Vec::with_capacity(target);
+    let mut seen_words = HashSet::with_capacity(target);
+    // well above the draws needed to collect every word of a keyspace as small as the target
+    let mut attempts = target.saturating_mul(100).max(1000);
+
+    while words.len() < target && attempts > 0 {
+        attempts -= 1;
+        let word: String = charsets
+            .iter()
+            .map(|charset| charset[rng.below(charset.len())])
+            .collect();
+
+        if seen_words.insert(word.clone()) {
+            words.push(word);
+        }
+    }
+
+    Ok(words)
+}
+
+/// Distinct characters of a class, charsets given with repeated characters included.
+///
+/// Characters classified otherwise by `config`, e.g. claimed by a custom class or uppercase
+/// letters once case is folded, are left out so every sample keeps the mask.
+fn class_charset(class: char, config: &MaskConfig) -> Result<Vec<char>, MaskError> {
+    let mut seen_chars = HashSet::new();
+    // split digits are classified as plain digits, their class depending on the position
+    let classified_as = match class {
+        'p' | 't' => 'd',
+        _ => class,
+    };
+    let charset: Vec<char> = match class {
+        'l' => Ok(('a'..='z').collect()),
+        'u' => Ok(('A'..='Z').collect()),
+        'd' => Ok(('0'..='9').collect()),
+        's' => Ok(config
+            .special_charset
+            .chars()
+            .filter(|&char| !(config.whitespace && WHITESPACE_CHARSET.contains(char)))
+            .collect()),
+        'w' if config.whitespace => Ok(WHITESPACE_CHARSET.chars().collect()),
+        'p' | 't' if config.split_digits => Ok(('0'..='9').collect()),
+        'a' if config.letter_merge_mode == LetterMergeMode::AnyLetter => {
+            Ok(('a'..='z').chain('A'..='Z').collect())
+        }
+        _ => config
+            .custom_classes
+            .iter()
+            .find(|custom_class| custom_class.letter == class)
+            .map(|custom_class| custom_class.charset.chars().collect())
+            .ok_or(MaskError::InvalidCharacter(class)),
+    }?;
+
+    Ok(charset
+        .into_iter()
+        .filter(|&char| classify_char(char, config) == Some(classified_as))
+        .filter(|&char| seen_chars.insert(char))
+        .collect())
+}
+
+/// SplitMix64 generator, good enough for reproducible samples without a dependency.
+struct SampleRng(u64);
+
+impl SampleRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{generate_masks_from_bufreader, position_charsets, sort_masks, ReadOptions};
+
+    #[test]
+    fn mask_generation() {
+        let word = "HelloFriend";
+        let mask = generate_mask(word, &MaskConfig::default()).unwrap();
+        assert_eq!(mask, "ullllulllll");
+    }
+
+    #[test]
+    fn word_analysis() {
+        let config = MaskConfig::default();
+        let (mask, position_chars) = analyze_word("Ab1!", &config).unwrap();
+
+        assert_eq!(mask, "ulds");
+        assert_eq!(
+            position_chars,
+            [
+                HashSet::from(['A']),
+                HashSet::from(['b']),
+                HashSet::from(['1']),
+                HashSet::from(['!'])
+            ]
+        );
+        assert_eq!(analyze_word("", &config), Err(MaskError::EmptyWord));
+    }
+
+    #[test]
+    fn hcmask_custom_charsets() {
+        let config = MaskConfig::default();
+        let wordlist = Cursor::new(b"Pass1\npass2\nPost1\nab\n");
+        let selected_masks = HashSet::from(["ullld".to_string()]);
+        let charsets =
+            position_charsets(wordlist, &selected_masks, &config, &ReadOptions::default()).unwrap();
+
+        assert_eq!(charsets.len(), 1);
+        assert_eq!(
+            to_hcmask_line("ullld", &charsets["ullld"], config.charset_sizes()),
+            "P,aost,1,?1?2?2?2?3"
+        );
+        let many: Vec<BTreeSet<char>> = "abcdef,?"
+            .chars()
+            .map(|char| BTreeSet::from([char]))
+            .collect();
+        assert_eq!(
+            to_hcmask_line("llllllss", &many, config.charset_sizes()),
+            "abcdef,\\,??,?1?1?1?1?1?1?2?2"
+        );
+        assert_eq!(to_hcmask_line("ld", &[], config.charset_sizes()), "?l?d");
+    }
+
+    #[test]
+    fn split_digit_masks() {
+        let config = MaskConfig::builder().split_digits(true).build();
+        let mask = |word| generate_mask(word, &config).unwrap();
+
+        assert_eq!(mask("12abc1980"), "ppllltttt");
+        assert_eq!(mask("ab12cd"), "llddll");
+        assert_eq!(mask("2024"), "dddd");
+        assert_eq!(mask("Pass!"), "ullls");
+        assert_eq!(
+            compute_mask_size("lltttt", usize::MAX, config.charset_sizes()),
+            Ok(Some(6760000))
+        );
+        assert_eq!(parse_hashcat_mask("?l?t"), Ok("lt".to_string()));
+    }
+
+    #[test]
+    fn empty_word_error() {
+        let config = MaskConfig::default();
+        assert_eq!(generate_mask("", &config), Err(MaskError::EmptyWord));
+
+        let mut wordlist = Cursor::new(b"\n\nabc\n\n");
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+        assert_eq!(mask_counts.masks.len(), 1);
+        assert_eq!(mask_counts.total_words, 1);
+        assert_eq!(mask_counts.skipped.words, 0);
+    }
+
+    #[test]
+    fn reused_mask_buffer() {
+        let config = MaskConfig::default();
+        let mut mask = String::from("stale");
+
+        assert_eq!(generate_mask_into("Abc1", &config, &mut mask), Ok(()));
+        assert_eq!(mask, "ulld");
+        assert_eq!(generate_mask_into("xy", &config, &mut mask), Ok(()));
+        assert_eq!(mask, "ll");
+        assert_eq!(
+            generate_mask_into("", &config, &mut mask),
+            Err(MaskError::EmptyWord)
+        );
+        assert_eq!(mask, "");
+    }
+
+    #[test]
+    fn hashcat_mask_round_trip() {
+        let mask = generate_mask("HelloFriend", &MaskConfig::default()).unwrap();
+        let hashcat_mask = to_hashcat_mask(&mask);
+        assert_eq!(hashcat_mask, "?u?l?l?l?l?u?l?l?l?l?l");
+        assert_eq!(parse_hashcat_mask(&hashcat_mask).unwrap(), mask);
+
+        assert_eq!(to_hashcat_mask(""), "");
+        assert_eq!(parse_hashcat_mask("").unwrap(), "");
+    }
+
+    #[test]
+    fn custom_class_hashcat_round_trip() {
+        let config = MaskConfig::builder()
+            .custom_class(CustomClass::new('v', "ae"))
+            .build();
+        let mask = generate_mask("Bad1", &config).unwrap();
+        let hashcat_mask = to_hashcat_mask(&mask);
+        assert_eq!(hashcat_mask, "?u?v?l?d");
+
+        assert_eq!(
+            parse_hashcat_mask_with(&hashcat_mask, &config).unwrap(),
+            mask
+        );
+        assert_eq!(
+            parse_hashcat_mask(&hashcat_mask),
+            Err(MaskError::UnknownClass('v'))
+        );
+        assert_eq!(
+            parse_hashcat_mask_with("?x", &config),
+            Err(MaskError::UnknownClass('x'))
+        );
+    }
+
+    #[test]
+    fn mask_classes() {
+        assert_eq!(
+            parse_mask("ulds").unwrap(),
+            [
+                MaskClass::Upper,
+                MaskClass::Lower,
+                MaskClass::Digit,
+                MaskClass::Special
+            ]
+        );
+        assert_eq!(parse_mask(""), Ok(Vec::new()));
+        assert_eq!(parse_mask("ulx"), Err(MaskError::UnknownClass('x')));
+
+        let mask: String = parse_mask("uLNd")
+            .unwrap()
+            .into_iter()
+            .map(MaskClass::letter)
+            .collect();
+        assert_eq!(mask, "uLNd");
+    }
+
+    #[test]
+    fn invalid_hashcat_mask() {
+        assert_eq!(
+            parse_hashcat_mask("?u?x"),
+            Err(MaskError::UnknownClass('x'))
+        );
+        assert!(parse_hashcat_mask("?u?").is_err());
+        assert!(parse_hashcat_mask("ul").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn computed_mask_json() {
+        let mask = super::ComputedMask {
+            mask: "ullll".to_string(),
+            size: 11881376,
+            count: 3,
+            cost: 3.0 / 11881376.0,
+            entropy_bits: 11881376f64.log2(),
+            over_budget: false,
+        };
+        let json = serde_json::to_value(&mask).unwrap();
+
+        assert_eq!(json["mask"], "ullll");
+        assert_eq!(json["size"], 11881376);
+        assert_eq!(json["count"], 3);
+        assert_eq!(json["cost"].as_f64().unwrap(), mask.cost);
+    }
+
+    #[test]
+    fn fold_case_masks() {
+        let config = MaskConfig::builder().fold_case(true).build();
+        let mut wordlist = Cursor::new(b"HELLO\nhello\nHello\nHello1");
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+
+        assert_eq!(mask_counts.masks.len(), 2);
+        assert_eq!(mask_counts.masks["lllll"], 3);
+        assert_eq!(mask_counts.masks["llllld"], 1);
+
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &config);
+        assert_eq!(mask_list[0].size, 26usize.pow(5));
+    }
+
+    #[test]
+    fn any_letter_masks() {
+        let config = MaskConfig::builder()
+            .letter_merge_mode(LetterMergeMode::AnyLetter)
+            .build();
+        let mut wordlist = Cursor::new(b"HELLO\nhello\nHello1");
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+
+        assert_eq!(mask_counts.masks["aaaaa"], 2);
+        assert_eq!(mask_counts.masks["aaaaad"], 1);
+        assert!(!config.fold_case());
+        assert_eq!(normalize_mask("?u?l?d", &config).unwrap(), "aad");
+
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &config);
+        assert_eq!(mask_list[0].size, 52usize.pow(5));
+    }
+
+    #[test]
+    fn unicode_masks() {
+        let config = MaskConfig::default();
+        assert_eq!(
+            generate_mask("Café1", &config),
+            Err(MaskError::InvalidCharacter('é'))
+        );
+
+        let config = MaskConfig::builder().unicode(true).build();
+        assert_eq!(generate_mask("Café1", &config).unwrap(), "ullLd");
+        assert_eq!(generate_mask("Пароль١", &config).unwrap(), "LLLLLLN");
+        assert_eq!(
+            generate_mask("€", &config),
+            Err(MaskError::InvalidCharacter('€'))
+        );
+        assert_eq!(
+            compute_mask_size("LN", usize::MAX, config.charset_sizes()),
+            Ok(Some(66 * 10))
+        );
+    }
+
+    #[test]
+    fn simple_masks() {
+        let config = MaskConfig::default();
+        assert_eq!(generate_simple_mask("password12", &config).unwrap(), "l8d2");
+        assert_eq!(simplify_mask("ulllldds"), "u1l4d2s1");
+        assert_eq!(simplify_mask(""), "");
+
+        let charset_sizes = config.charset_sizes();
+        assert_eq!(
+            compute_simple_mask_keyspace("l8d2", charset_sizes),
+            compute_mask_keyspace("lllllllldd", charset_sizes)
+        );
+        assert_eq!(
+            compute_simple_mask_keyspace("l12", charset_sizes),
+            Ok(26u128.pow(12))
+        );
+        assert_eq!(
+            compute_simple_mask_keyspace("l8d", charset_sizes),
+            Err(MaskError::InvalidCharacter('d'))
+        );
+        assert_eq!(
+            compute_simple_mask_keyspace("x2", charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+    }
+
+    #[test]
+    fn special_multiplier_counts_characters() {
+        assert_eq!(
+            CharsetSizes::default().special,
+            SPECIAL_CHARSET.chars().count()
+        );
+        assert_eq!(char_count("ab€£"), 4);
+
+        let config = MaskConfig::builder().special_charset("!€£").build();
+        assert_eq!(config.charset_sizes().special, 3);
+        assert_eq!(generate_mask("a€", &config).unwrap(), "ls");
+    }
+
+    #[test]
+    fn ascii_class_table_matches_classification() {
+        let configs = [
+            MaskConfig::default(),
+            MaskConfig::builder()
+                .fold_case(true)
+                .whitespace(true)
+                .special_charset("!?")
+                .custom_class(CustomClass::new('v', "aeiou€"))
+                .build(),
+        ];
+
+        for config in &configs {
+            for byte in 0..128u8 {
+                let char = byte as char;
+                assert_eq!(
+                    config.ascii_classes[byte as usize].map(char::from),
+                    classify_char(char, config),
+                    "{:?}",
+                    char
+                );
+            }
+        }
+        assert_eq!(generate_mask("Ha€! \t", &configs[1]).unwrap(), "lvvsww");
+        assert_eq!(
+            generate_mask("Hé", &configs[1]),
+            Err(MaskError::InvalidCharacter('é'))
+        );
+    }
+
+    #[test]
+    fn special_charset_presets() {
+        let config = MaskConfig::builder()
+            .special_charset(SpecialCharset::AsciiSpecial.charset())
+            .build();
+        assert_eq!(config.charset_sizes().special, 32);
+        assert_eq!(
+            generate_mask("a b", &config),
+            Err(MaskError::InvalidCharacter(' '))
+        );
+
+        let config = MaskConfig::builder()
+            .special_charset(SpecialCharset::TopRow.charset())
+            .build();
+        assert_eq!(config.charset_sizes().special, 10);
+        assert_eq!(generate_mask("pa$$(", &config).unwrap(), "llsss");
+        assert_eq!(
+            compute_mask_size("ls", usize::MAX, config.charset_sizes()),
+            Ok(Some(26 * 10))
+        );
+        assert_eq!(SpecialCharset::HashcatS.charset(), SPECIAL_CHARSET);
+    }
+
+    #[test]
+    fn whitespace_class() {
+        let config = MaskConfig::default();
+        assert_eq!(generate_mask("my pass", &config).unwrap(), "llsllll");
+        assert_eq!(
+            generate_mask("my\tpass", &config),
+            Err(MaskError::InvalidCharacter('\t'))
+        );
+
+        let config = MaskConfig::builder().whitespace(true).build();
+        assert_eq!(generate_mask("my pass!", &config).unwrap(), "llwlllls");
+        assert_eq!(generate_mask("\tmy1", &config).unwrap(), "wlld");
+        assert_eq!(
+            compute_mask_size("ws", usize::MAX, config.charset_sizes()),
+            Ok(Some(2 * 32))
+        );
+        assert_eq!(parse_hashcat_mask("?w?l").unwrap(), "wl");
+        assert_eq!(sample_words("w", 5, &config, 1).unwrap().len(), 2);
+        assert_eq!(sample_words("s", 50, &config, 1).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn custom_vowel_class() {
+        let config = MaskConfig::builder()
+            .custom_class(CustomClass::new('v', "aeiou"))
+            .build();
+
+        assert_eq!(generate_mask("Hello1", &config).unwrap(), "uvllvd");
+        assert_eq!(config.charset_sizes().multiplier('v'), Some(5));
+        assert_eq!(
+            compute_mask_size("uvllvd", usize::MAX, config.charset_sizes()),
+            Ok(Some(26 * 5 * 26 * 26 * 5 * 10))
+        );
+
+        let word = sample_words("vv", 1, &config, 0).unwrap().remove(0);
+        assert!(word.chars().all(|char| "aeiou".contains(char)));
+    }
+
+    #[test]
+    fn unknown_characters_handling() {
+        let config = MaskConfig::builder()
+            .on_unknown(OnUnknown::Class('e', 1000))
+            .build();
+        assert_eq!(generate_mask("love😀1", &config).unwrap(), "lllled");
+        assert_eq!(generate_mask("a\u{7f}", &config).unwrap(), "le");
+        assert_eq!(
+            compute_mask_size("le", usize::MAX, config.charset_sizes()),
+            Ok(Some(26 * 1000))
+        );
+
+        let wordlist = "love😀\nlove\nlové";
+        let counted = |on_unknown| {
+            let config = MaskConfig::builder().on_unknown(on_unknown).build();
+            generate_masks_from_bufreader(&mut Cursor::new(wordlist), &config).unwrap()
+        };
+        let reported = counted(OnUnknown::Error);
+        assert_eq!((reported.total_words, reported.skipped.words), (3, 2));
+        let skipped = counted(OnUnknown::Skip);
+        assert_eq!((skipped.total_words, skipped.skipped.words), (1, 0));
+        let classified = counted(OnUnknown::Class('e', 1000));
+        assert_eq!(classified.masks.get("lllle"), Some(&1));
+        assert_eq!(classified.masks.get("llle"), Some(&1));
+    }
+
+    #[test]
+    fn sampled_words_match_mask() {
+        let config = MaskConfig::default();
+        let words = sample_words("ullllddddss", 20, &config, 42).unwrap();
+
+        assert_eq!(words.len(), 20);
+        for word in &words {
+            assert_eq!(generate_mask(word, &config).unwrap(), "ullllddddss");
+        }
+        assert_eq!(words, sample_words("ullllddddss", 20, &config, 42).unwrap());
+        assert_ne!(words, sample_words("ullllddddss", 20, &config, 7).unwrap());
+
+        assert_eq!(sample_words("d", 50, &config, 1).unwrap().len(), 10);
+        assert!(sample_words("dx", 5, &config, 1).is_err());
+    }
+
+    #[test]
+    fn sampled_words_of_repeated_chars() {
+        let config = MaskConfig::builder()
+            .special_charset("!!")
+            .custom_class(CustomClass::new('v', "aa"))
+            .build();
+
+        assert_eq!(sample_words("s", 2, &config, 0).unwrap(), ["!"]);
+        assert_eq!(sample_words("vs", 2, &config, 0).unwrap(), ["a!"]);
+    }
+
+    #[test]
+    fn sampled_words_with_custom_classes() {
+        let config = MaskConfig::builder()
+            .custom_class(CustomClass::new('v', "aeiou"))
+            .custom_class(CustomClass::new('h', "!?"))
+            .build();
+
+        for mask in ["ullvd", "llsh", "vvuu"] {
+            for word in sample_words(mask, 50, &config, 3).unwrap() {
+                assert_eq!(generate_mask(&word, &config).unwrap(), mask);
+            }
+        }
+
+        let folded_config = MaskConfig::builder().fold_case(true).build();
+        assert!(sample_words("u", 5, &folded_config, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn mask_size_computation() {
+        let mask = "ullllulllll";
+        let mask_size = compute_mask_size(mask, usize::MAX, &CharsetSizes::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(mask_size, 3670344486987776);
+    }
+
+    #[test]
+    fn custom_special_charset() {
+        let config = MaskConfig::builder().special_charset("!@#$").build();
+        let mut wordlist = Cursor::new(b"Pass!\nPass~\n1234@#");
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+
+        assert!(generate_mask("Pass~", &config).is_err());
+        assert_eq!(mask_counts.masks.len(), 2);
+        assert_eq!(mask_counts.masks["ddddss"], 1);
+
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &config);
+        let policy_mask = mask_list.iter().find(|mask| mask.mask == "ullls").unwrap();
+        assert_eq!(policy_mask.size, 26 * 26 * 26 * 26 * 4);
+    }
+
+    #[test]
+    fn custom_charset_sizes() {
+        let charset_sizes = CharsetSizes {
+            lower: 10,
+            upper: 5,
+            digit: 4,
+            special: 2,
+            ..CharsetSizes::default()
+        };
+        assert_eq!(
+            compute_mask_size("ulds", usize::MAX, &charset_sizes),
+            Ok(Some(5 * 10 * 4 * 2))
+        );
+
+        let config = MaskConfig::builder()
+            .special_charset("!")
+            .charset_sizes(charset_sizes.clone())
+            .build();
+        assert_eq!(config.special_charset(), "!");
+        assert_eq!(config.charset_sizes(), &charset_sizes);
+        let mut wordlist = Cursor::new(b"Pass1!");
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &config);
+        assert_eq!(mask_list[0].size, 5 * 10 * 10 * 10 * 4 * 2);
+    }
+
+    #[test]
+    fn special_keyspace_override() {
+        let config = MaskConfig::builder()
+            .charset_sizes(CharsetSizes {
+                special: 20,
+                ..CharsetSizes::default()
+            })
+            .special_keyspace(5)
+            .build();
+
+        assert_eq!(config.special_keyspace(), Some(5));
+        assert_eq!(config.charset_sizes().special, 5);
+        assert_eq!(generate_mask("a~", &config), Ok("ls".to_string()));
+        assert_eq!(
+            sort_masks(&HashMap::from([("ls".to_string(), 1)]), usize::MAX, &config)[0].size,
+            26 * 5
+        );
+
+        assert!(std::panic::catch_unwind(|| MaskConfig::builder().special_keyspace(0)).is_err());
+        let empty_special = CharsetSizes {
+            special: 0,
+            ..CharsetSizes::default()
+        };
+        assert_eq!(compute_mask_size("ls", 1000, &empty_special), Ok(Some(0)));
+    }
+
+    #[test]
+    fn mask_keyspace_beyond_usize() {
+        let mask = "s".repeat(15);
+        let charset_sizes = CharsetSizes::default();
+
+        assert_eq!(
+            compute_mask_size(&mask, usize::MAX, &charset_sizes),
+            Ok(None)
+        );
+        assert_eq!(
+            compute_mask_keyspace(&mask, &charset_sizes),
+            Ok(33u128.pow(15))
+        );
+        assert_eq!(
+            compute_mask_keyspace(&"s".repeat(30), &charset_sizes),
+            Ok(u128::MAX)
+        );
+        assert_eq!(
+            compute_mask_keyspace("ullllulllll", &charset_sizes),
+            Ok(3670344486987776)
+        );
+    }
+
+    #[test]
+    fn mask_entropy() {
+        let charset_sizes = CharsetSizes::default();
+        let entropy_bits = compute_mask_entropy("ullllulllll", &charset_sizes).unwrap();
+        assert!((entropy_bits - 3670344486987776f64.log2()).abs() < 1e-9);
+
+        let long_mask = "s".repeat(40);
+        assert_eq!(
+            compute_mask_size(&long_mask, usize::MAX, &charset_sizes),
+            Ok(None)
+        );
+        assert!(
+            (compute_mask_entropy(&long_mask, &charset_sizes).unwrap() - 40.0 * 33f64.log2()).abs()
+                < 1e-9
+        );
+
+        let mut wordlist = Cursor::new(b"1234");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+        assert!((mask_list[0].entropy_bits - 10000f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mask_fits_budget() {
+        let charset_sizes = CharsetSizes::default();
+
+        assert_eq!(mask_fits("dd", 100, &charset_sizes), Ok(true));
+        assert_eq!(mask_fits("dd", 99, &charset_sizes), Ok(false));
+        assert_eq!(mask_fits("", 1, &charset_sizes), Ok(true));
+        assert_eq!(
+            mask_fits(&"d".repeat(19), usize::MAX, &charset_sizes),
+            Ok(true)
+        );
+        assert_eq!(
+            mask_fits(&"d".repeat(20), usize::MAX, &charset_sizes),
+            Ok(false)
+        );
+        assert_eq!(mask_fits(&"s".repeat(1000), 10, &charset_sizes), Ok(false));
+        assert_eq!(
+            mask_fits("dx", usize::MAX, &charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+    }
+
+    #[test]
+    fn unknown_mask_class() {
+        let charset_sizes = CharsetSizes::default();
+
+        assert_eq!(
+            compute_mask_size("x", usize::MAX, &charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+        assert_eq!(
+            compute_mask_size("dddx", 10, &charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+        assert_eq!(
+            compute_mask_keyspace("lx", &charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+        assert_eq!(
+            compute_mask_entropy("xl", &charset_sizes),
+            Err(MaskError::UnknownClass('x'))
+        );
+
+        let masks_counts = HashMap::from([("x".to_string(), 3), ("d".to_string(), 1)]);
+        let mask_list = sort_masks(&masks_counts, usize::MAX, &MaskConfig::default());
+        assert_eq!(mask_list.len(), 1);
+        assert_eq!(mask_list[0].mask, "d");
+    }
+
+    #[test]
+    fn mask_cost() {
+        let mask = "ullllulllll";
+        let mask_size = compute_mask_size(mask, usize::MAX, &CharsetSizes::default())
+            .unwrap()
+            .unwrap();
+        let mask_occurrences = 1000;
+        let mask_cost = compute_mask_cost(mask_size, mask_occurrences);
+        assert_eq!(mask_cost, 2.7245398995795416e-13);
+    }
+
+    #[test]
+    fn equivalent_masks_normalized() {
+        let config = MaskConfig::builder()
+            .fold_case(true)
+            .split_digits(true)
+            .custom_class(CustomClass::new('v', "aeiou"))
+            .build();
+
+        assert_eq!(
+            normalize_mask("?u?l?v?d?d", &config),
+            Ok("llvtt".to_string())
+        );
+        assert_eq!(normalize_mask("ullvdd", &config), Ok("lllvtt".to_string()));
+        assert_eq!(
+            normalize_mask("?l?l?w", &config),
+            normalize_mask("lls", &config)
+        );
+        assert_eq!(
+            normalize_mask("?d?d?d", &MaskConfig::default()),
+            normalize_mask("ppp", &MaskConfig::default())
+        );
+        assert_eq!(
+            normalize_mask("?l?", &config),
+            Err(MaskError::InvalidCharacter('?'))
+        );
+        assert_eq!(
+            normalize_mask("lx", &config),
+            Err(MaskError::UnknownClass('x'))
+        );
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use crate::BUILTIN_CLASSES;
+
+        use super::{
+            compute_mask_size, generate_mask, parse_hashcat_mask, to_hashcat_mask, CharsetSizes,
+            MaskConfig,
+        };
+
+        fn builtin_mask() -> impl Strategy<Value = String> {
+            proptest::string::string_regex(&format!("[{}]{{0,32}}", BUILTIN_CLASSES)).unwrap()
+        }
+
+        proptest! {
+            #[test]
+            fn ascii_masks_keep_length(word in "[\\x00-\\x7f]{1,64}") {
+                match generate_mask(&word, &MaskConfig::default()) {
+                    Ok(mask) => {
+                        prop_assert_eq!(mask.chars().count(), word.len());
+                        prop_assert!(mask.chars().all(|class| "luds".contains(class)));
+                    }
+                    // only control characters fall outside the default classes
+                    Err(_) => prop_assert!(word.chars().any(|char| char.is_ascii_control())),
+                }
+            }
+
+            #[test]
+            fn mask_size_grows_with_length(mask in "[luds]{0,24}", class in "[luds]") {
+                let charset_sizes = CharsetSizes::default();
+                let size = compute_mask_size(&mask, usize::MAX, &charset_sizes).unwrap();
+                let longer_mask = format!("{}{}", mask, class);
+                let longer_size = compute_mask_size(&longer_mask, usize::MAX, &charset_sizes).unwrap();
+
+                match (size, longer_size) {
+                    (Some(size), Some(longer_size)) => prop_assert!(size < longer_size),
+                    (_, None) => {}
+                    (None, Some(_)) => prop_assert!(false, "{} fits but not its prefix", longer_mask),
+                }
+            }
+
+            #[test]
+            fn hashcat_masks_round_trip(mask in builtin_mask()) {
+                let hashcat_mask = to_hashcat_mask(&mask);
+                prop_assert_eq!(hashcat_mask.len(), mask.len() * 2);
+                prop_assert_eq!(parse_hashcat_mask(&hashcat_mask).unwrap(), mask);
+            }
+
+            #[test]
+            fn word_masks_round_trip(word in "[ -~]{1,64}") {
+                let mask = generate_mask(&word, &MaskConfig::default()).unwrap();
+                prop_assert_eq!(parse_hashcat_mask(&to_hashcat_mask(&mask)).unwrap(), mask);
+            }
+        }
+    }
+}
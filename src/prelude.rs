@@ -0,0 +1,31 @@
+//! Common types of the crate, to glob import with `use speedy_mask::prelude::*`.
+
+pub use crate::io::{FileError, MaskCounts, ReadOptions, SkipReport};
+pub use crate::mask::{
+    CharsetSizes, ComputedMask, CostMetric, CustomClass, MaskClass, MaskConfig, MaskConfigBuilder,
+    MaskError,
+};
+pub use crate::select::{
+    AnalysisResult, MaskFilter, ParseOptions, PolicyFilter, SelectionStrategy, SortKey,
+};
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::parse_reader;
+
+    #[test]
+    fn prelude_types() {
+        let options = ParseOptions {
+            sort_key: SortKey::Count,
+            config: MaskConfigBuilder::new().fold_case(true).build(),
+            ..ParseOptions::default()
+        };
+        let result: AnalysisResult = parse_reader(&mut Cursor::new(b"Hi\nho"), &options).unwrap();
+
+        assert_eq!(result.masks[0].mask, "ll");
+        assert_eq!(result.masks[0].count, 2);
+    }
+}
@@ -0,0 +1,1247 @@
+//! Sorting of counted masks and their selection within a keyspace budget.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::io::{count_files, read_mask_counts, FileError, MaskCounts, ReadOptions, SkipReport};
+use crate::mask::{
+    compute_mask_entropy, compute_mask_size, log2_mask_cost, ComputedMask, CostMetric, MaskClass,
+    MaskConfig, DEFAULT_COST_EPSILON,
+};
+use crate::stats::combined_coverage;
+
+/// Granularity of occurrence counts in the coverage knapsack, bounding its table size.
+const COVERAGE_BUCKETS: usize = 2048;
+
+/// Constraints a mask must satisfy to be kept by [`sort_masks_filtered`].
+#[derive(Debug, Clone)]
+pub struct MaskFilter {
+    /// Shortest accepted mask length, inclusive.
+    pub min_length: usize,
+    /// Longest accepted mask length, inclusive.
+    pub max_length: usize,
+    /// Fewest occurrences a mask needs to be kept.
+    pub min_occurrence: usize,
+    /// Largest keyspace of a single mask, inclusive, whatever its count and the total budget.
+    ///
+    /// Larger masks are dropped like the other filtered ones, without being over the budget.
+    pub max_mask_keyspace: usize,
+    /// Keep the masks over the budget instead of dropping them, see [`ComputedMask::over_budget`].
+    pub keep_over_budget: bool,
+}
+
+impl MaskFilter {
+    pub fn accepts(&self, mask: &str, count: usize) -> bool {
+        (self.min_length..=self.max_length).contains(&mask.len()) && count >= self.min_occurrence
+    }
+}
+
+impl Default for MaskFilter {
+    fn default() -> Self {
+        Self {
+            min_length: 0,
+            max_length: usize::MAX,
+            min_occurrence: 0,
+            max_mask_keyspace: usize::MAX,
+            keep_over_budget: false,
+        }
+    }
+}
+
+/// Password policy a mask must be able to satisfy, e.g. at least one upper, one digit and
+/// one special character with a length of 8 to 16.
+#[derive(Debug, Clone)]
+pub struct PolicyFilter {
+    /// Shortest accepted mask length, inclusive.
+    pub min_length: usize,
+    /// Longest accepted mask length, inclusive.
+    pub max_length: usize,
+    /// Classes a mask must contain at least once.
+    pub required_classes: Vec<MaskClass>,
+}
+
+impl PolicyFilter {
+    pub fn accepts(&self, mask: &str) -> bool {
+        (self.min_length..=self.max_length).contains(&mask.len())
+            && self.required_classes.iter().all(|class| match class {
+                // split digits are digits all the same
+                MaskClass::Digit => mask.contains(['d', 'p', 't']),
+                // so are letters of either case
+                MaskClass::Lower | MaskClass::Upper => mask.contains([class.letter(), 'a']),
+                _ => mask.contains(class.letter()),
+            })
+    }
+}
+
+impl Default for PolicyFilter {
+    fn default() -> Self {
+        Self {
+            min_length: 0,
+            max_length: usize::MAX,
+            required_classes: Vec::new(),
+        }
+    }
+}
+
+/// Drop the masks that cannot satisfy `policy`, keeping the order of the others.
+pub fn filter_policy(masks: &mut Vec<ComputedMask>, policy: &PolicyFilter) {
+    masks.retain(|mask| policy.accepts(&mask.mask));
+}
+
+/// Ordering applied to computed masks, ties are broken by ascending mask string.
+///
+/// Masks with nearly the same cost are ordered by ascending length first. Over-budget masks come
+/// last whatever the key, and every key gives a total order, so masks counted in a `HashMap` are
+/// always sorted the same regardless of its iteration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    /// Highest occurrences per keyspace first.
+    #[default]
+    Cost,
+    /// Most frequent first.
+    Count,
+    /// Smallest keyspace first.
+    Size,
+    /// Lexical order.
+    Mask,
+}
+
+impl SortKey {
+    pub fn sort(self, masks: &mut [ComputedMask]) {
+        self.sort_with_epsilon(masks, DEFAULT_COST_EPSILON);
+    }
+
+    /// Same as [`SortKey::sort`], costs within `cost_epsilon` of each other being tied.
+    pub fn sort_with_epsilon(self, masks: &mut [ComputedMask], cost_epsilon: f64) {
+        masks.sort_by(|mask_0, mask_1| {
+            let ordering = match self {
+                SortKey::Cost => compare_costs(mask_0.cost, mask_1.cost),
+                SortKey::Count => mask_1.count.cmp(&mask_0.count),
+                SortKey::Size => mask_0.size.cmp(&mask_1.size),
+                SortKey::Mask => Ordering::Equal,
+            };
+            mask_0
+                .over_budget
+                .cmp(&mask_1.over_budget)
+                .then(ordering)
+                .then_with(|| mask_0.mask.cmp(&mask_1.mask))
+        });
+
+        if self == SortKey::Cost {
+            prefer_shorter_on_ties(masks, cost_epsilon);
+        }
+    }
+}
+
+/// Reorder runs of cost sorted masks tied with the first mask of the run by ascending length.
+///
+/// Runs are anchored on their first mask, so the order stays deterministic even though the
+/// tolerance is not transitive.
+fn prefer_shorter_on_ties(masks: &mut [ComputedMask], cost_epsilon: f64) {
+    let mut start = 0;
+
+    while start < masks.len() {
+        let leader_cost = masks[start].cost;
+        let tied_count = masks[start + 1..]
+            .iter()
+            .take_while(|mask| costs_tie(leader_cost, mask.cost, cost_epsilon))
+            .count();
+        let end = start + 1 + tied_count;
+
+        masks[start..end].sort_by(|mask_0, mask_1| {
+            mask_0
+                .over_budget
+                .cmp(&mask_1.over_budget)
+                .then(mask_0.mask.len().cmp(&mask_1.mask.len()))
+                .then_with(|| mask_0.mask.cmp(&mask_1.mask))
+        });
+        start = end;
+    }
+}
+
+/// Whether two costs differ by at most `cost_epsilon` relative to the largest, NaN never ties.
+fn costs_tie(cost_0: f64, cost_1: f64, cost_epsilon: f64) -> bool {
+    let difference = (cost_0 - cost_1).abs();
+    cost_0 == cost_1
+        || (difference.is_finite() && difference <= cost_epsilon * cost_0.abs().max(cost_1.abs()))
+}
+
+/// Descending cost order, NaN costs are placed last so sorting never panics.
+fn compare_costs(cost_0: f64, cost_1: f64) -> Ordering {
+    match (cost_0.is_nan(), cost_1.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => cost_1.total_cmp(&cost_0),
+    }
+}
+
+/// How masks are picked within the keyspace budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    /// Walk the sorted masks and take each one still fitting in the budget.
+    #[default]
+    Greedy,
+    /// Maximize the covered words with a knapsack over occurrence counts.
+    ///
+    /// Counts are scaled down to a couple thousand steps to bound the table, the remaining
+    /// budget is then filled greedily and the greedy selection is kept if it still covers more.
+    Coverage,
+}
+
+/// Options driving [`parse_file`].
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Keyspace budget shared by all selected masks.
+    pub maximum_size: usize,
+    pub filter: MaskFilter,
+    pub config: MaskConfig,
+    pub read: ReadOptions,
+    pub sort_key: SortKey,
+    /// Reverse the [`Self::sort_key`] order before the selection, so [`Self::top`] keeps the
+    /// last masks, e.g. the lowest cost ones.
+    pub reverse: bool,
+    pub strategy: SelectionStrategy,
+    /// Keep only the first selected masks.
+    pub top: Option<usize>,
+    /// Masks failing the policy are left out before selection, and not reported as rejected.
+    pub policy: Option<PolicyFilter>,
+    /// Native masks left out before selection, like the policy ones.
+    pub excluded_masks: HashSet<String>,
+    /// Measure the duration of each step into [`AnalysisResult::timings`].
+    pub timings: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            maximum_size: usize::MAX,
+            filter: MaskFilter::default(),
+            config: MaskConfig::default(),
+            read: ReadOptions::default(),
+            sort_key: SortKey::default(),
+            reverse: false,
+            strategy: SelectionStrategy::default(),
+            top: None,
+            policy: None,
+            excluded_masks: HashSet::new(),
+            timings: false,
+        }
+    }
+}
+
+/// Masks of `masks_counts` fitting in `maximum_size`, with their sizes and costs, by
+/// [`SortKey::Cost`].
+///
+/// Costs are descending, masks within `config`'s cost epsilon of each other ascending by length,
+/// then by mask string, so sorting the same counts twice gives identical vectors.
+pub fn sort_masks(
+    masks_counts: &HashMap<String, usize>,
+    maximum_size: usize,
+    config: &MaskConfig,
+) -> Vec<ComputedMask> {
+    sort_masks_by(masks_counts, maximum_size, config, SortKey::Cost)
+}
+
+/// Same as [`sort_masks`], ordering masks by `sort_key` instead of cost.
+pub fn sort_masks_by(
+    masks_counts: &HashMap<String, usize>,
+    maximum_size: usize,
+    config: &MaskConfig,
+    sort_key: SortKey,
+) -> Vec<ComputedMask> {
+    let mut sorted_masks =
+        compute_masks(masks_counts, maximum_size, &MaskFilter::default(), config);
+    sort_key.sort_with_epsilon(&mut sorted_masks, config.cost_epsilon());
+    sorted_masks
+}
+
+/// Same as [`sort_masks`], ranking the masks of `masks_counts` by their count in `weights`,
+/// e.g. the counts of another corpus, instead of their own.
+///
+/// Masks missing from `weights` fall back to their count in `masks_counts`, and the counts of
+/// the returned masks are the ones their cost was computed with.
+pub fn sort_masks_with_weights(
+    masks_counts: &HashMap<String, usize>,
+    weights: &HashMap<String, usize>,
+    maximum_size: usize,
+    config: &MaskConfig,
+) -> Vec<ComputedMask> {
+    let weighted_counts: HashMap<String, usize> = masks_counts
+        .iter()
+        .map(|(mask, &count)| {
+            let weight = weights.get(mask).copied().unwrap_or(count);
+            (mask.clone(), weight)
+        })
+        .collect();
+    sort_masks(&weighted_counts, maximum_size, config)
+}
+
+/// Same as [`sort_masks`], discarding masks rejected by `filter` before their cost is computed.
+pub fn sort_masks_filtered(
+    masks_counts: &HashMap<String, usize>,
+    maximum_size: usize,
+    filter: &MaskFilter,
+    config: &MaskConfig,
+) -> Vec<ComputedMask> {
+    let mut sorted_masks = compute_masks(masks_counts, maximum_size, filter, config);
+    SortKey::Cost.sort_with_epsilon(&mut sorted_masks, config.cost_epsilon());
+    sorted_masks
+}
+
+/// Compute size and cost of every mask accepted by `filter` and fitting in `maximum_size`,
+/// in no particular order.
+///
+/// Masks holding an unknown class letter are skipped as well. With
+/// [`CostMetric::Log2CountPerKeyspace`] and no budget, masks whose keyspace overflows `usize` are
+/// kept as [`ComputedMask::over_budget`] ones.
+pub fn compute_masks(
+    masks_counts: &HashMap<String, usize>,
+    maximum_size: usize,
+    filter: &MaskFilter,
+    config: &MaskConfig,
+) -> Vec<ComputedMask> {
+    let mut computed_masks = Vec::with_capacity(masks_counts.len());
+    // without budget, only masks whose keyspace overflows usize fail the size computation
+    let ranks_overflowing = maximum_size == usize::MAX
+        && matches!(config.cost_metric(), CostMetric::Log2CountPerKeyspace);
+
+    for (mask, &mask_count) in masks_counts {
+        if !filter.accepts(mask, mask_count) {
+            continue;
+        }
+
+        let (mask_size, over_budget) =
+            match compute_mask_size(mask, maximum_size, config.charset_sizes()) {
+                Ok(Some(mask_size)) => (mask_size, false),
+                Ok(None) if filter.keep_over_budget || ranks_overflowing => (usize::MAX, true),
+                // mask is too big, or holds an unknown class
+                Ok(None) | Err(_) => continue,
+            };
+        if mask_size > filter.max_mask_keyspace {
+            continue;
+        }
+        let entropy_bits = compute_mask_entropy(mask, config.charset_sizes())
+            .expect("classes checked by the size computation");
+        let mask_cost = match config.cost_metric() {
+            CostMetric::Log2CountPerKeyspace => log2_mask_cost(entropy_bits, mask_count),
+            _ if over_budget => 0.0,
+            cost_metric => cost_metric.cost(mask_size, mask_count),
+        };
+        computed_masks.push(ComputedMask {
+            mask: mask.clone(),
+            size: mask_size,
+            count: mask_count,
+            cost: mask_cost,
+            entropy_bits,
+            over_budget,
+        });
+    }
+
+    computed_masks
+}
+
+/// Outcome of [`parse_file`].
+#[derive(Debug, Clone)]
+pub struct AnalysisResult {
+    /// Selected masks, sorted by [`ParseOptions::sort_key`].
+    pub masks: Vec<ComputedMask>,
+    /// Keyspace used by the selected masks.
+    pub used_space: usize,
+    /// Masks left out by the budget or [`ParseOptions::top`], sorted the same way.
+    pub rejected: Vec<ComputedMask>,
+    /// Non-empty lines read, including skipped words.
+    pub total_words: usize,
+    /// Distinct masks of the wordlist, before filtering.
+    pub distinct_masks: usize,
+    pub skipped: SkipReport,
+    /// Words left uncounted by [`ReadOptions::max_distinct_masks`].
+    pub capped_words: usize,
+    /// Step durations, only measured with [`ParseOptions::timings`].
+    pub timings: Option<Timings>,
+}
+
+/// Time spent in each step of [`parse_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Reading and classifying the words.
+    pub generation_time: Duration,
+    /// Computing and sorting the masks.
+    pub sort_time: Duration,
+    /// Picking the masks within the budget.
+    pub selection_time: Duration,
+}
+
+impl AnalysisResult {
+    /// Fraction of the wordlist matched by the selected masks.
+    pub fn coverage(&self) -> f64 {
+        combined_coverage(&self.masks, self.total_words)
+    }
+}
+
+/// Select the most cost-effective masks of a file within the keyspace budget.
+///
+/// A path of [`STDIN_PATH`] reads the wordlist from the standard input, and a directory is
+/// walked with [`ReadOptions::walk_depth`].
+///
+/// [`STDIN_PATH`]: crate::STDIN_PATH
+pub fn parse_file<P>(path: P, options: &ParseOptions) -> Result<AnalysisResult, FileError>
+where
+    P: AsRef<Path>,
+{
+    parse_files(&[path], options)
+}
+
+/// Same as [`parse_file`] with the mask counts of every wordlist summed before selection.
+pub fn parse_files<P>(paths: &[P], options: &ParseOptions) -> Result<AnalysisResult, FileError>
+where
+    P: AsRef<Path>,
+{
+    let (mask_counts, generation_time) = timed(options.timings, || count_files(paths, options));
+    Ok(select_from_counts(mask_counts?, options, generation_time))
+}
+
+/// Same as [`parse_files`], also selecting masks out of each wordlist on its own.
+///
+/// Returns the result of every wordlist, in order, then the result of the merged counts.
+pub fn parse_files_separately<P>(
+    paths: &[P],
+    options: &ParseOptions,
+) -> Result<(Vec<AnalysisResult>, AnalysisResult), FileError>
+where
+    P: AsRef<Path>,
+{
+    let mut file_results = Vec::with_capacity(paths.len());
+    let mut merged_counts = MaskCounts::default();
+
+    for path in paths {
+        let (mask_counts, generation_time) =
+            timed(options.timings, || count_files(&[path], options));
+        let mask_counts = mask_counts?;
+        merged_counts.merge(mask_counts.clone());
+        file_results.push(select_from_counts(mask_counts, options, generation_time));
+    }
+
+    Ok((file_results, analyze_counts(merged_counts, options)))
+}
+
+/// Same as [`parse_file`] over an already opened wordlist.
+///
+/// Masks whose keyspace alone exceeds the budget are still skipped, and reported as rejected.
+pub fn parse_reader<R>(line_reader: &mut R, options: &ParseOptions) -> io::Result<AnalysisResult>
+where
+    R: BufRead,
+{
+    let (mask_counts, generation_time) =
+        timed(options.timings, || read_mask_counts(line_reader, options));
+    Ok(select_from_counts(mask_counts?, options, generation_time))
+}
+
+/// Run `step`, measuring its duration only when `enabled`.
+fn timed<T>(enabled: bool, step: impl FnOnce() -> T) -> (T, Option<Duration>) {
+    let start = enabled.then(Instant::now);
+    let output = step();
+    (output, start.map(|start| start.elapsed()))
+}
+
+/// Select masks out of already gathered counts, e.g. merged with [`load_counts`].
+///
+/// With [`ParseOptions::timings`], the generation time is left at zero.
+///
+/// [`load_counts`]: crate::load_counts
+pub fn analyze_counts(mask_counts: MaskCounts, options: &ParseOptions) -> AnalysisResult {
+    select_from_counts(
+        mask_counts,
+        options,
+        options.timings.then_some(Duration::ZERO),
+    )
+}
+
+fn select_from_counts(
+    mask_counts: MaskCounts,
+    options: &ParseOptions,
+    generation_time: Option<Duration>,
+) -> AnalysisResult {
+    let (sorted_masks, sort_time) = timed(options.timings, || {
+        candidate_masks(&mask_counts.masks, options)
+    });
+    let ((masks, used_space, rejected), selection_time) = timed(options.timings, || {
+        let mut selected = selection_flags(&sorted_masks, options.maximum_size, options.strategy);
+
+        if let Some(top) = options.top {
+            selected
+                .iter_mut()
+                .filter(|is_selected| **is_selected)
+                .skip(top)
+                .for_each(|is_selected| *is_selected = false);
+        }
+
+        split_selection(sorted_masks, selected)
+    });
+    let timings = generation_time.zip(sort_time).zip(selection_time).map(
+        |((generation_time, sort_time), selection_time)| Timings {
+            generation_time,
+            sort_time,
+            selection_time,
+        },
+    );
+
+    AnalysisResult {
+        masks,
+        used_space,
+        rejected,
+        total_words: mask_counts.total_words,
+        distinct_masks: mask_counts.masks.len(),
+        skipped: mask_counts.skipped,
+        capped_words: mask_counts.capped_words,
+        timings,
+    }
+}
+
+/// Masks passing the filters, policy and exclusions of `options`, in their printing order.
+fn candidate_masks(masks: &HashMap<String, usize>, options: &ParseOptions) -> Vec<ComputedMask> {
+    // oversized masks are computed too so they can be reported as rejected, unless they are kept
+    // as over budget ones
+    let maximum_size = if options.filter.keep_over_budget {
+        options.maximum_size
+    } else {
+        usize::MAX
+    };
+    let mut sorted_masks = compute_masks(masks, maximum_size, &options.filter, &options.config);
+    if let Some(policy) = &options.policy {
+        filter_policy(&mut sorted_masks, policy);
+    }
+    if !options.excluded_masks.is_empty() {
+        sorted_masks.retain(|mask| !options.excluded_masks.contains(&mask.mask));
+    }
+    options
+        .sort_key
+        .sort_with_epsilon(&mut sorted_masks, options.config.cost_epsilon());
+    if options.reverse {
+        sorted_masks.reverse();
+    }
+    sorted_masks
+}
+
+/// Selected masks of already gathered counts, yielded as soon as each is picked so they can be
+/// printed without waiting for the whole selection.
+///
+/// Sorting still needs every mask first. Masks are always picked greedily, whatever
+/// [`ParseOptions::strategy`], and neither rejected masks nor timings are reported.
+pub fn stream_selected_masks(
+    mask_counts: &MaskCounts,
+    options: &ParseOptions,
+) -> impl Iterator<Item = ComputedMask> {
+    let sorted_masks = candidate_masks(&mask_counts.masks, options);
+    select_within_budget(sorted_masks, options.maximum_size).take(options.top.unwrap_or(usize::MAX))
+}
+
+/// Lazily pick the masks fitting together in `maximum_size`, like the greedy strategy of
+/// [`select_masks`], so top-N, policy and budget steps can be chained in any order.
+///
+/// Masks are taken in the order they come, over-budget ones are never picked.
+pub fn select_within_budget<I>(masks: I, maximum_size: usize) -> impl Iterator<Item = ComputedMask>
+where
+    I: IntoIterator<Item = ComputedMask>,
+{
+    let mut used_space = 0;
+
+    masks.into_iter().filter(move |mask| {
+        let fits = fits_in_budget(mask, maximum_size, used_space);
+        if fits {
+            used_space += mask.size;
+        }
+        fits
+    })
+}
+
+/// Pick masks fitting together in `maximum_size`, keeping their order.
+///
+/// Returns the selected masks and the keyspace they use.
+pub fn select_masks(
+    sorted_masks: Vec<ComputedMask>,
+    maximum_size: usize,
+    strategy: SelectionStrategy,
+) -> (Vec<ComputedMask>, usize) {
+    let selected = selection_flags(&sorted_masks, maximum_size, strategy);
+    let (selected_masks, used_space, _) = split_selection(sorted_masks, selected);
+    (selected_masks, used_space)
+}
+
+fn selection_flags(
+    sorted_masks: &[ComputedMask],
+    maximum_size: usize,
+    strategy: SelectionStrategy,
+) -> Vec<bool> {
+    // nothing fits in an empty budget, there is no need to build the coverage table
+    if maximum_size == 0 {
+        return vec![false; sorted_masks.len()];
+    }
+
+    match strategy {
+        SelectionStrategy::Greedy => select_greedy(sorted_masks, maximum_size),
+        SelectionStrategy::Coverage => select_coverage(sorted_masks, maximum_size),
+    }
+}
+
+/// Split masks into the selected ones, the keyspace they use and the rejected ones, both kept in
+/// their original order.
+fn split_selection(
+    sorted_masks: Vec<ComputedMask>,
+    selected: Vec<bool>,
+) -> (Vec<ComputedMask>, usize, Vec<ComputedMask>) {
+    let (selected_masks, rejected_masks): (Vec<_>, Vec<_>) = sorted_masks
+        .into_iter()
+        .zip(selected)
+        .partition(|(_, is_selected)| *is_selected);
+    let selected_masks: Vec<ComputedMask> =
+        selected_masks.into_iter().map(|(mask, _)| mask).collect();
+    let used_space = selected_masks.iter().map(|mask| mask.size).sum();
+
+    (
+        selected_masks,
+        used_space,
+        rejected_masks.into_iter().map(|(mask, _)| mask).collect(),
+    )
+}
+
+/// Whether `mask` fits in what `used_space` leaves of `maximum_size`, a used space over the
+/// budget leaving nothing rather than underflowing.
+pub(crate) fn fits_in_budget(mask: &ComputedMask, maximum_size: usize, used_space: usize) -> bool {
+    !mask.over_budget
+        && maximum_size
+            .checked_sub(used_space)
+            .is_some_and(|space_left| mask.size <= space_left)
+}
+
+fn select_greedy(masks: &[ComputedMask], maximum_size: usize) -> Vec<bool> {
+    let mut used_space = 0;
+
+    masks
+        .iter()
+        .map(|mask| {
+            if fits_in_budget(mask, maximum_size, used_space) {
+                used_space += mask.size;
+                return true;
+            }
+            false
+        })
+        .collect()
+}
+
+fn select_coverage(masks: &[ComputedMask], maximum_size: usize) -> Vec<bool> {
+    let total_count = masks
+        .iter()
+        .fold(0usize, |total, mask| total.saturating_add(mask.count));
+    let scale = total_count.div_ceil(COVERAGE_BUCKETS).max(1);
+    let values: Vec<usize> = masks.iter().map(|mask| mask.count / scale).collect();
+    let maximum_value: usize = values.iter().sum();
+
+    // masks with a non-zero scaled value are at most COVERAGE_BUCKETS, keeping the table small
+    let candidates: Vec<usize> = (0..masks.len())
+        .filter(|&index| {
+            values[index] > 0 && !masks[index].over_budget && masks[index].size <= maximum_size
+        })
+        .collect();
+    let mut smallest_sizes = vec![usize::MAX; maximum_value + 1];
+    let mut kept = vec![false; candidates.len() * (maximum_value + 1)];
+    smallest_sizes[0] = 0;
+
+    for (row, &index) in candidates.iter().enumerate() {
+        let value = values[index];
+
+        for total_value in (value..=maximum_value).rev() {
+            let size = match smallest_sizes[total_value - value].checked_add(masks[index].size) {
+                Some(size) if size <= maximum_size => size,
+                _ => continue,
+            };
+
+            if size < smallest_sizes[total_value] {
+                smallest_sizes[total_value] = size;
+                kept[row * (maximum_value + 1) + total_value] = true;
+            }
+        }
+    }
+
+    let mut selected = vec![false; masks.len()];
+    let mut total_value = (0..=maximum_value)
+        .rev()
+        .find(|&total_value| smallest_sizes[total_value] <= maximum_size)
+        .unwrap_or(0);
+    let mut used_space = smallest_sizes[total_value];
+
+    for (row, &index) in candidates.iter().enumerate().rev() {
+        if kept[row * (maximum_value + 1) + total_value] {
+            selected[index] = true;
+            total_value -= values[index];
+        }
+    }
+
+    // spend what is left of the budget on the remaining masks
+    for (mask, is_selected) in masks.iter().zip(selected.iter_mut()) {
+        if !*is_selected && fits_in_budget(mask, maximum_size, used_space) {
+            used_space += mask.size;
+            *is_selected = true;
+        }
+    }
+
+    let greedy = select_greedy(masks, maximum_size);
+    let covered = |selection: &[bool]| -> usize {
+        masks
+            .iter()
+            .zip(selection)
+            .filter(|(_, &is_selected)| is_selected)
+            .fold(0usize, |total, (mask, _)| total.saturating_add(mask.count))
+    };
+
+    if covered(&greedy) > covered(&selected) {
+        greedy
+    } else {
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        generate_mask, generate_masks_from_bufreader, normalize_mask, read_mask_list, ComputedMask,
+        CostMetric, MaskClass, MaskConfig, MaskCounts, DEFAULT_COST_EPSILON,
+    };
+
+    #[test]
+    fn cost_metrics() {
+        let masks_counts = HashMap::from([("dd".to_string(), 2), ("llllllll".to_string(), 1000)]);
+        let order = |cost_metric| -> Vec<String> {
+            let config = MaskConfig::builder().cost_metric(cost_metric).build();
+            sort_masks(&masks_counts, usize::MAX, &config)
+                .into_iter()
+                .map(|mask| mask.mask)
+                .collect()
+        };
+
+        assert_eq!(order(CostMetric::CountPerKeyspace), ["dd", "llllllll"]);
+        assert_eq!(order(CostMetric::CountPerLog2Keyspace), ["llllllll", "dd"]);
+        assert_eq!(
+            order(CostMetric::Custom(|_, count| count as f64)),
+            ["llllllll", "dd"]
+        );
+        assert_eq!(CostMetric::CountPerLog2Keyspace.cost(1024, 50), 5.0);
+        assert_eq!(CostMetric::Log2CountPerKeyspace.cost(1024, 64), -4.0);
+    }
+
+    #[test]
+    fn log2_cost_ranks_overflowing_masks() {
+        let masks_counts = HashMap::from([
+            ("d".repeat(30), 1000),
+            ("d".repeat(40), 1000),
+            ("dd".to_string(), 2),
+        ]);
+        let config = MaskConfig::builder()
+            .cost_metric(CostMetric::Log2CountPerKeyspace)
+            .build();
+        let sorted_masks = sort_masks(&masks_counts, usize::MAX, &config);
+
+        let masks: Vec<(usize, bool)> = sorted_masks
+            .iter()
+            .map(|mask| (mask.mask.len(), mask.over_budget))
+            .collect();
+        assert_eq!(masks, [(2, false), (30, true), (40, true)]);
+        assert!(sorted_masks.iter().all(|mask| mask.cost.is_finite()));
+        assert_eq!(sort_masks(&masks_counts, 1000, &config).len(), 1);
+    }
+
+    #[test]
+    fn per_file_and_combined_results() {
+        let directory = std::env::temp_dir();
+        let first_path = directory.join("speedy_mask_per_file_first.txt");
+        let second_path = directory.join("speedy_mask_per_file_second.txt");
+        std::fs::write(&first_path, b"Hello\nabc").unwrap();
+        std::fs::write(&second_path, b"abc\n1234\nxyz").unwrap();
+
+        let (file_results, combined) =
+            parse_files_separately(&[&first_path, &second_path], &ParseOptions::default()).unwrap();
+        std::fs::remove_file(first_path).unwrap();
+        std::fs::remove_file(second_path).unwrap();
+
+        let totals: Vec<usize> = file_results
+            .iter()
+            .map(|result| result.total_words)
+            .collect();
+        assert_eq!(totals, [2, 3]);
+        assert_eq!(file_results[0].distinct_masks, 2);
+        assert_eq!(combined.total_words, 5);
+        assert_eq!(combined.distinct_masks, 3);
+        assert_eq!(combined.masks[0].count, 3);
+    }
+
+    #[test]
+    fn sort_masks_list() {
+        let mut wordlist = Cursor::new(b"Hello\nFriend\nPassword\nP@$$w0rd");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+
+        assert_eq!(mask_list[0].mask, "ullll");
+    }
+
+    #[test]
+    fn streamed_selection_matches_greedy() {
+        let counts = [
+            ("Password1", 5),
+            ("abc", 40),
+            ("abcd", 3),
+            ("123456", 8),
+            ("Abc!", 1),
+        ];
+        let mut mask_counts = MaskCounts::default();
+        for (word, count) in counts {
+            let mask = generate_mask(word, &MaskConfig::default()).unwrap();
+            *mask_counts.masks.entry(mask).or_insert(0) += count;
+            mask_counts.total_words += count;
+        }
+        let options = ParseOptions {
+            maximum_size: 2_000_000,
+            top: Some(3),
+            ..ParseOptions::default()
+        };
+
+        let streamed: Vec<String> = stream_selected_masks(&mask_counts, &options)
+            .map(|mask| mask.mask)
+            .collect();
+        let collected: Vec<String> = analyze_counts(mask_counts, &options)
+            .masks
+            .into_iter()
+            .map(|mask| mask.mask)
+            .collect();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, collected);
+    }
+
+    #[test]
+    fn sort_masks_deterministic() {
+        let masks: Vec<String> = (0..8)
+            .map(|bits| {
+                (0..3)
+                    .map(|i| if bits >> i & 1 == 1 { 'u' } else { 'l' })
+                    .collect()
+            })
+            .collect();
+        let masks_counts: HashMap<String, usize> =
+            masks.iter().map(|mask| (mask.clone(), 1)).collect();
+        let reversed_counts: HashMap<String, usize> =
+            masks.iter().rev().map(|mask| (mask.clone(), 1)).collect();
+        let config = MaskConfig::default();
+
+        let sorted_masks: Vec<String> = sort_masks(&masks_counts, usize::MAX, &config)
+            .into_iter()
+            .map(|mask| mask.mask)
+            .collect();
+        let mut expected = masks.clone();
+        expected.sort();
+        assert_eq!(sorted_masks, expected);
+        for counts in [&masks_counts, &reversed_counts] {
+            let resorted: Vec<String> = sort_masks(counts, usize::MAX, &config)
+                .into_iter()
+                .map(|mask| mask.mask)
+                .collect();
+            assert_eq!(resorted, sorted_masks);
+        }
+    }
+
+    #[test]
+    fn sort_masks_against_weights() {
+        let masks_counts = HashMap::from([
+            ("lll".to_string(), 10),
+            ("ddd".to_string(), 1),
+            ("ullll".to_string(), 3),
+        ]);
+        let weights = HashMap::from([("ddd".to_string(), 500), ("dd".to_string(), 9)]);
+        let sorted_masks =
+            sort_masks_with_weights(&masks_counts, &weights, usize::MAX, &MaskConfig::default());
+
+        let masks: Vec<(&str, usize)> = sorted_masks
+            .iter()
+            .map(|mask| (mask.mask.as_str(), mask.count))
+            .collect();
+        assert_eq!(masks, [("ddd", 500), ("lll", 10), ("ullll", 3)]);
+    }
+
+    #[test]
+    fn sort_masks_by_keys() {
+        let mut wordlist = Cursor::new(b"abc\nxyz\nqwe\nHello\nWorld\n12345\n123");
+        let config = MaskConfig::default();
+        let mask_counts = generate_masks_from_bufreader(&mut wordlist, &config).unwrap();
+        let sorted_with = |sort_key| -> Vec<String> {
+            sort_masks_by(&mask_counts.masks, usize::MAX, &config, sort_key)
+                .into_iter()
+                .map(|mask| mask.mask)
+                .collect()
+        };
+
+        assert_eq!(
+            sorted_with(SortKey::Count),
+            ["lll", "ullll", "ddd", "ddddd"]
+        );
+        assert_eq!(sorted_with(SortKey::Size), ["ddd", "lll", "ddddd", "ullll"]);
+        assert_eq!(sorted_with(SortKey::Mask), ["ddd", "ddddd", "lll", "ullll"]);
+        assert_eq!(sorted_with(SortKey::Cost), ["ddd", "lll", "ddddd", "ullll"]);
+    }
+
+    #[test]
+    fn sort_cost_ties_prefer_shorter() {
+        let computed_mask = |mask: &str, cost| ComputedMask {
+            mask: mask.to_string(),
+            size: 1,
+            count: 1,
+            cost,
+            entropy_bits: 0.0,
+            over_budget: false,
+        };
+        let masks = vec![
+            computed_mask("aaaa", 1.0),
+            computed_mask("bb", 1.0 - DEFAULT_COST_EPSILON / 2.0),
+            computed_mask("ccc", 0.99),
+            computed_mask("d", 0.98),
+        ];
+        let sorted_with = |cost_epsilon| -> Vec<String> {
+            let mut masks = masks.clone();
+            SortKey::Cost.sort_with_epsilon(&mut masks, cost_epsilon);
+            masks.into_iter().map(|mask| mask.mask).collect()
+        };
+
+        assert_eq!(
+            sorted_with(DEFAULT_COST_EPSILON),
+            ["bb", "aaaa", "ccc", "d"]
+        );
+        assert_eq!(sorted_with(0.015), ["bb", "ccc", "aaaa", "d"]);
+        assert_eq!(sorted_with(0.0), ["aaaa", "bb", "ccc", "d"]);
+
+        let config = MaskConfig::builder().cost_epsilon(0.015).build();
+        assert_eq!(config.cost_epsilon(), 0.015);
+    }
+
+    #[test]
+    fn sort_nan_cost_last() {
+        let computed_mask = |mask: &str, cost| ComputedMask {
+            mask: mask.to_string(),
+            size: 1,
+            count: 1,
+            cost,
+            entropy_bits: 0.0,
+            over_budget: false,
+        };
+        let mut masks = vec![
+            computed_mask("d", f64::NAN),
+            computed_mask("l", 0.5),
+            computed_mask("s", f64::NAN),
+            computed_mask("u", f64::INFINITY),
+        ];
+        SortKey::Cost.sort(&mut masks);
+
+        let masks: Vec<&str> = masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["u", "l", "d", "s"]);
+    }
+
+    #[test]
+    fn sort_masks_min_occurrence() {
+        let mut wordlist = Cursor::new(b"abc\nxyz\nHello\nWorld\nFriend");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let filter = MaskFilter {
+            min_occurrence: 2,
+            ..MaskFilter::default()
+        };
+        let mask_list =
+            sort_masks_filtered(&mask_counts.masks, 20000, &filter, &MaskConfig::default());
+
+        let masks: Vec<&str> = mask_list.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["lll"]);
+    }
+
+    #[test]
+    fn mask_over_budget_rejected() {
+        let mask = ComputedMask {
+            mask: "llll".to_string(),
+            size: 456976,
+            count: 1,
+            cost: 1.0 / 456976.0,
+            entropy_bits: 456976f64.log2(),
+            over_budget: false,
+        };
+
+        for strategy in [SelectionStrategy::Greedy, SelectionStrategy::Coverage] {
+            let (selected, used_space) = select_masks(vec![mask.clone()], 1000, strategy);
+            assert!(selected.is_empty());
+            assert_eq!(used_space, 0);
+        }
+        assert_eq!(select_within_budget([mask.clone()], 1000).count(), 0);
+        // a used space past the budget leaves no room instead of underflowing
+        assert!(!fits_in_budget(&mask, 1000, 2000));
+    }
+
+    #[test]
+    fn coverage_selection_beats_greedy() {
+        let computed_mask = |mask: &str, size: usize, count: usize| ComputedMask {
+            mask: mask.to_string(),
+            size,
+            count,
+            cost: count as f64 / size as f64,
+            entropy_bits: (size as f64).log2(),
+            over_budget: false,
+        };
+        // greedy takes the cheapest mask first and can no longer afford the two others
+        let sorted_masks = vec![
+            computed_mask("d", 10, 5),
+            computed_mask("dd", 100, 40),
+            computed_mask("lll", 95, 35),
+        ];
+
+        let (greedy, greedy_space) =
+            select_masks(sorted_masks.clone(), 200, SelectionStrategy::Greedy);
+        let (optimal, optimal_space) =
+            select_masks(sorted_masks.clone(), 200, SelectionStrategy::Coverage);
+
+        let masks = |selected: &[ComputedMask]| -> Vec<String> {
+            selected.iter().map(|mask| mask.mask.clone()).collect()
+        };
+        assert_eq!(masks(&greedy), ["d", "dd"]);
+        assert_eq!(greedy_space, 110);
+        assert_eq!(masks(&optimal), ["dd", "lll"]);
+        assert_eq!(optimal_space, 195);
+
+        let lazy: Vec<ComputedMask> = select_within_budget(sorted_masks.clone(), 200).collect();
+        assert_eq!(masks(&lazy), masks(&greedy));
+        let policy = PolicyFilter {
+            required_classes: vec![MaskClass::Lower],
+            ..PolicyFilter::default()
+        };
+        let filtered_first: Vec<ComputedMask> = select_within_budget(
+            sorted_masks
+                .into_iter()
+                .filter(|mask| policy.accepts(&mask.mask)),
+            200,
+        )
+        .take(1)
+        .collect();
+        assert_eq!(masks(&filtered_first), ["lll"]);
+    }
+
+    #[test]
+    fn parse_from_reader() {
+        let mut wordlist = Cursor::new(b"Hello\nWorld\nabc\n12345678901234567890");
+        let options = ParseOptions {
+            maximum_size: 20000,
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut wordlist, &options).unwrap();
+
+        assert_eq!(result.total_words, 4);
+        assert_eq!(result.distinct_masks, 3);
+        assert_eq!(result.masks.len(), 1);
+        assert_eq!(result.masks[0].mask, "lll");
+        assert_eq!(result.used_space, 17576);
+        assert_eq!(result.coverage(), 0.25);
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].mask, "ullll");
+        assert_eq!(result.rejected[0].size, 11881376);
+        assert_eq!(result.timings, None);
+
+        let options = ParseOptions {
+            timings: true,
+            ..options
+        };
+        let result = parse_reader(&mut Cursor::new(b"abc"), &options).unwrap();
+        assert!(result.timings.is_some());
+    }
+
+    #[test]
+    fn parse_with_empty_budget() {
+        let options = ParseOptions {
+            maximum_size: 0,
+            ..ParseOptions::default()
+        };
+        for strategy in [SelectionStrategy::Greedy, SelectionStrategy::Coverage] {
+            let options = ParseOptions {
+                strategy,
+                ..options.clone()
+            };
+            let result = parse_reader(&mut Cursor::new(b"Hello\nabc\n1"), &options).unwrap();
+
+            assert!(result.masks.is_empty());
+            assert_eq!(result.used_space, 0);
+            assert_eq!(result.rejected.len(), 3);
+        }
+    }
+
+    #[test]
+    fn parse_keeps_masks_over_space_limit() {
+        let options = ParseOptions {
+            maximum_size: 100_000,
+            sort_key: SortKey::Count,
+            top: Some(1),
+            filter: MaskFilter {
+                keep_over_budget: true,
+                ..MaskFilter::default()
+            },
+            ..ParseOptions::default()
+        };
+        let wordlist = b"Hello\nWorld\nHappy\nabc\nxyz\n1";
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+
+        assert_eq!(result.masks[0].mask, "lll");
+        let rejected: Vec<(&str, bool)> = result
+            .rejected
+            .iter()
+            .map(|mask| (mask.mask.as_str(), mask.over_budget))
+            .collect();
+        assert_eq!(rejected, [("d", false), ("ullll", true)]);
+    }
+
+    #[test]
+    fn parse_top_masks() {
+        let wordlist = b"Hello\nWorld\nabc\nxyz\nqwe\n1234";
+        let options = ParseOptions {
+            sort_key: SortKey::Count,
+            top: Some(2),
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+
+        let masks: Vec<&str> = result.masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["lll", "ullll"]);
+        assert_eq!(result.used_space, 17576 + 11881376);
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].mask, "dddd");
+
+        let options = ParseOptions {
+            reverse: true,
+            ..options
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+
+        let masks: Vec<&str> = result.masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["dddd", "ullll"]);
+        assert_eq!(result.rejected[0].mask, "lll");
+    }
+
+    #[test]
+    fn policy_compliant_masks() {
+        let policy = PolicyFilter {
+            min_length: 8,
+            max_length: 16,
+            required_classes: vec![MaskClass::Upper, MaskClass::Digit, MaskClass::Special],
+        };
+        assert!(policy.accepts("ulllllds"));
+        assert!(!policy.accepts("ullllds"));
+        assert!(!policy.accepts("lllllllds"));
+
+        let wordlist = b"password\npassword\nP@ssw0rd\nPass1234";
+        let options = ParseOptions {
+            policy: Some(policy),
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+        let masks: Vec<&str> = result.masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["usllldll"]);
+        assert!(result.rejected.is_empty());
+    }
+
+    #[test]
+    fn excluded_masks_never_selected() {
+        let config = MaskConfig::default();
+        let excluded_masks = read_mask_list(Cursor::new(b"?l?l?l\n\nud\n"), &config).unwrap();
+        assert_eq!(
+            excluded_masks,
+            HashSet::from(["lll".to_string(), "ud".to_string()])
+        );
+        let error = read_mask_list(Cursor::new(b"lll\n?x"), &config).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(normalize_mask("?u?d", &config), Ok("ud".to_string()));
+
+        // `lll` has by far the highest cost
+        let wordlist = b"abc\nabc\nabc\nabc\nHello\n";
+        let options = ParseOptions {
+            excluded_masks,
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+        let masks: Vec<&str> = result.masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["ullll"]);
+        assert!(result.rejected.is_empty());
+    }
+
+    #[test]
+    fn over_budget_masks_kept_last() {
+        let masks_counts = HashMap::from([
+            ("dd".to_string(), 1),
+            ("llll".to_string(), 50),
+            ("d".to_string(), 2),
+        ]);
+        let filter = MaskFilter {
+            keep_over_budget: true,
+            ..MaskFilter::default()
+        };
+        let sorted_masks =
+            sort_masks_filtered(&masks_counts, 1000, &filter, &MaskConfig::default());
+
+        let masks: Vec<(&str, bool)> = sorted_masks
+            .iter()
+            .map(|mask| (mask.mask.as_str(), mask.over_budget))
+            .collect();
+        assert_eq!(masks, [("d", false), ("dd", false), ("llll", true)]);
+        assert_eq!(sorted_masks[2].size, usize::MAX);
+
+        let (selected_masks, used_space) =
+            select_masks(sorted_masks, usize::MAX, SelectionStrategy::Greedy);
+        assert_eq!(selected_masks.len(), 2);
+        assert_eq!(used_space, 110);
+    }
+
+    #[test]
+    fn sort_masks_keyspace_cap() {
+        let masks_counts = HashMap::from([
+            ("dd".to_string(), 1),
+            ("llll".to_string(), 50),
+            ("d".to_string(), 2),
+        ]);
+        let filter = MaskFilter {
+            max_mask_keyspace: 100,
+            keep_over_budget: true,
+            ..MaskFilter::default()
+        };
+        let sorted_masks =
+            sort_masks_filtered(&masks_counts, usize::MAX, &filter, &MaskConfig::default());
+
+        let masks: Vec<&str> = sorted_masks.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(masks, ["d", "dd"]);
+    }
+
+    #[test]
+    fn sort_masks_length_bounds() {
+        let mut wordlist = Cursor::new(b"Hello\nFriend\nPassword\nP@$$w0rd\nabc");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let filter = MaskFilter {
+            min_length: 5,
+            max_length: 6,
+            ..MaskFilter::default()
+        };
+        let mask_list = sort_masks_filtered(
+            &mask_counts.masks,
+            usize::MAX,
+            &filter,
+            &MaskConfig::default(),
+        );
+
+        let mut masks: Vec<&str> = mask_list.iter().map(|mask| mask.mask.as_str()).collect();
+        masks.sort_unstable();
+        assert_eq!(masks, ["ullll", "ulllll"]);
+    }
+}
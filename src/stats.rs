@@ -0,0 +1,590 @@
+//! Statistics over wordlists and selected masks.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::io::{count_files, lines, masks, weighted_words, FileError, ReadOptions, SkipReport};
+use crate::mask::{
+    compute_mask_keyspace, generate_mask_into, CharsetSizes, ComputedMask, MaskConfig, MaskError,
+};
+use crate::select::{compute_masks, ParseOptions, SortKey};
+
+/// Re-read a wordlist and count the words whose mask is one of `masks`, to check the counts
+/// of a selection.
+///
+/// Lines are read as described by `read_options`, weights and sampling counting as they do in
+/// the mask counts.
+pub fn verify_coverage<R>(
+    line_reader: R,
+    masks: &[ComputedMask],
+    config: &MaskConfig,
+    read_options: &ReadOptions,
+) -> io::Result<usize>
+where
+    R: BufRead,
+{
+    let selected_masks: HashSet<&str> = masks.iter().map(|mask| mask.mask.as_str()).collect();
+    let mut matched_words = 0usize;
+    let mut mask = String::new();
+
+    for word in weighted_words(line_reader, read_options) {
+        let (word, weight) = word?;
+        if generate_mask_into(&word, config, &mut mask).is_ok()
+            && selected_masks.contains(mask.as_str())
+        {
+            matched_words = matched_words.saturating_add(weight);
+        }
+    }
+
+    Ok(matched_words.saturating_mul(read_options.sample_every()))
+}
+
+/// Distinct characters classified as `s` in the words of a wordlist, words that cannot be
+/// classified left out.
+///
+/// Meant for a first pass sizing [`CharsetSizes::special`] after the symbols actually used.
+///
+/// [`CharsetSizes::special`]: crate::CharsetSizes::special
+pub fn observed_special_chars<R>(line_reader: R, config: &MaskConfig) -> io::Result<BTreeSet<char>>
+where
+    R: BufRead,
+{
+    let mut special_chars = BTreeSet::new();
+    let mut mask = String::new();
+
+    for word in lines(line_reader) {
+        let word = word?;
+        if generate_mask_into(&word, config, &mut mask).is_ok() {
+            special_chars.extend(
+                word.chars()
+                    .zip(mask.chars())
+                    .filter(|&(_, class)| class == 's')
+                    .map(|(char, _)| char),
+            );
+        }
+    }
+
+    Ok(special_chars)
+}
+
+/// Lazily yield the words of a wordlist whose mask is not in `selected_masks`, words that cannot
+/// be classified included. Blank lines are left out.
+///
+/// Lines are read as described by `read_options`, weighted words being yielded once.
+pub fn uncovered_words<'a, R>(
+    line_reader: R,
+    selected_masks: &'a HashSet<String>,
+    config: &'a MaskConfig,
+    read_options: &'a ReadOptions,
+) -> impl Iterator<Item = io::Result<String>> + 'a
+where
+    R: BufRead + 'a,
+{
+    let mut mask = String::new();
+
+    weighted_words(line_reader, read_options)
+        .map(|word| word.map(|(word, _)| word))
+        .filter(move |word| match word {
+            Ok(word) => match generate_mask_into(word, config, &mut mask) {
+                Ok(()) => !selected_masks.contains(&mask),
+                Err(MaskError::EmptyWord) => false,
+                Err(_) => true,
+            },
+            Err(_) => true,
+        })
+}
+
+/// Characters seen at each position of the words of every mask in `selected_masks`, as needed by
+/// [`to_hcmask_line`].
+///
+/// Lines are read as described by `read_options`.
+///
+/// [`to_hcmask_line`]: crate::to_hcmask_line
+pub fn position_charsets<R>(
+    line_reader: R,
+    selected_masks: &HashSet<String>,
+    config: &MaskConfig,
+    read_options: &ReadOptions,
+) -> io::Result<HashMap<String, Vec<BTreeSet<char>>>>
+where
+    R: BufRead,
+{
+    let mut charsets: HashMap<String, Vec<BTreeSet<char>>> = HashMap::new();
+    let mut mask = String::new();
+
+    for word in weighted_words(line_reader, read_options) {
+        let (word, _) = word?;
+        if generate_mask_into(&word, config, &mut mask).is_err() || !selected_masks.contains(&mask)
+        {
+            continue;
+        }
+
+        let position_chars = charsets
+            .entry(mask.clone())
+            .or_insert_with(|| vec![BTreeSet::new(); word.chars().count()]);
+        for (chars, char) in position_chars.iter_mut().zip(word.chars()) {
+            chars.insert(char);
+        }
+    }
+
+    Ok(charsets)
+}
+
+/// Count mask classes per position over every classified word of the wordlist.
+///
+/// Words only contribute to the positions they occupy, so the totals decrease along the vector.
+pub fn position_stats<R>(
+    line_reader: &mut R,
+    config: &MaskConfig,
+) -> io::Result<Vec<HashMap<char, usize>>>
+where
+    R: BufRead,
+{
+    let mut positions_counts: Vec<HashMap<char, usize>> = Vec::new();
+
+    for mask in masks(line_reader, config) {
+        let mask = match mask? {
+            Ok(mask) => mask,
+            Err(_) => continue,
+        };
+
+        for (position, class) in mask.chars().enumerate() {
+            if positions_counts.len() <= position {
+                positions_counts.push(HashMap::new());
+            }
+            *positions_counts[position].entry(class).or_insert(0) += 1;
+        }
+    }
+
+    Ok(positions_counts)
+}
+
+/// Share of each class at one position of the words, see [`position_fractions`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ClassFractions {
+    pub position: usize,
+    /// Number of words long enough to reach the position.
+    pub words: usize,
+    pub lowercase: f64,
+    pub uppercase: f64,
+    /// Digits, including the split `p` and `t` ones.
+    pub digit: f64,
+    pub special: f64,
+    /// Every other class, e.g. Unicode, whitespace or custom ones.
+    pub other: f64,
+}
+
+/// Fractions of each class per position of [`position_stats`] counts, normalized by the number
+/// of words reaching the position so every entry sums to 1.
+pub fn position_fractions(positions_counts: &[HashMap<char, usize>]) -> Vec<ClassFractions> {
+    positions_counts
+        .iter()
+        .enumerate()
+        .map(|(position, classes_counts)| {
+            let words: usize = classes_counts.values().sum();
+            let mut fractions = ClassFractions {
+                position,
+                words,
+                ..ClassFractions::default()
+            };
+
+            for (&class, &count) in classes_counts {
+                let fraction = count as f64 / words as f64;
+                match class {
+                    'l' => fractions.lowercase += fraction,
+                    'u' => fractions.uppercase += fraction,
+                    'd' | 'p' | 't' => fractions.digit += fraction,
+                    's' => fractions.special += fraction,
+                    _ => fractions.other += fraction,
+                }
+            }
+
+            fractions
+        })
+        .collect()
+}
+
+/// Count words per length, ignoring blank lines.
+///
+/// Lengths are measured in characters rather than bytes, so `été` has a length of 3.
+pub fn length_distribution<R>(line_reader: &mut R) -> io::Result<BTreeMap<usize, usize>>
+where
+    R: BufRead,
+{
+    let mut lengths_counts = BTreeMap::new();
+
+    for word in lines(line_reader) {
+        let length = word?.chars().count();
+
+        if length > 0 {
+            *lengths_counts.entry(length).or_insert(0) += 1;
+        }
+    }
+
+    Ok(lengths_counts)
+}
+
+/// Every mask of a wordlist along with aggregate numbers, see [`analyze_files`].
+#[derive(Debug, Clone)]
+pub struct MaskDistribution {
+    /// Masks accepted by the filter, sorted by [`ParseOptions::sort_key`].
+    pub masks: Vec<ComputedMask>,
+    pub total_words: usize,
+    /// Distinct masks of the wordlist, before filtering.
+    pub distinct_masks: usize,
+    /// Keyspace of every mask accepted by the filter, even those too large to be listed,
+    /// saturating at `u128::MAX`.
+    pub total_keyspace: u128,
+    pub skipped: SkipReport,
+}
+
+/// Compute every mask of the wordlists, ignoring the keyspace budget, selection and
+/// [`ParseOptions::top`].
+///
+/// Only masks whose keyspace overflows `usize` are missing from the list.
+pub fn analyze_files<P>(paths: &[P], options: &ParseOptions) -> Result<MaskDistribution, FileError>
+where
+    P: AsRef<Path>,
+{
+    let mask_counts = count_files(paths, options)?;
+    let mut masks = compute_masks(
+        &mask_counts.masks,
+        usize::MAX,
+        &options.filter,
+        &options.config,
+    );
+    options
+        .sort_key
+        .sort_with_epsilon(&mut masks, options.config.cost_epsilon());
+    if options.reverse {
+        masks.reverse();
+    }
+    // masks overflowing usize are left out of the list but still count without a cap
+    let max_mask_keyspace = match options.filter.max_mask_keyspace {
+        usize::MAX => u128::MAX,
+        max_mask_keyspace => max_mask_keyspace as u128,
+    };
+    let total_keyspace = mask_counts
+        .masks
+        .iter()
+        .filter(|(mask, &count)| options.filter.accepts(mask, count))
+        // unknown classes are skipped from the listed masks, and from the total
+        .map(|(mask, _)| compute_mask_keyspace(mask, options.config.charset_sizes()).unwrap_or(0))
+        .filter(|&keyspace| keyspace <= max_mask_keyspace)
+        .fold(0u128, u128::saturating_add);
+
+    Ok(MaskDistribution {
+        masks,
+        total_words: mask_counts.total_words,
+        distinct_masks: mask_counts.masks.len(),
+        total_keyspace,
+        skipped: mask_counts.skipped,
+    })
+}
+
+/// Masks bucketed by length, each bucket sorted by [`SortKey::Cost`].
+pub fn group_by_length(masks: &[ComputedMask]) -> BTreeMap<usize, Vec<ComputedMask>> {
+    let mut groups: BTreeMap<usize, Vec<ComputedMask>> = BTreeMap::new();
+    for mask in masks {
+        groups
+            .entry(mask.mask.chars().count())
+            .or_default()
+            .push(mask.clone());
+    }
+
+    for group in groups.values_mut() {
+        SortKey::Cost.sort(group);
+    }
+
+    groups
+}
+
+/// Fraction of `total_words` matched by `masks`, `0.0` for an empty wordlist.
+///
+/// A mask listed several times is only counted once.
+pub fn combined_coverage(masks: &[ComputedMask], total_words: usize) -> f64 {
+    if total_words == 0 {
+        return 0.0;
+    }
+
+    let mut seen_masks = HashSet::with_capacity(masks.len());
+    let covered_words: usize = masks
+        .iter()
+        .filter(|mask| seen_masks.insert(mask.mask.as_str()))
+        .fold(0usize, |total, mask| total.saturating_add(mask.count));
+    covered_words as f64 / total_words as f64
+}
+
+/// Number of distinct masks of `masks_counts` and their combined keyspace, the cost of covering
+/// every word without budget.
+///
+/// Masks holding an unknown class add nothing to the keyspace, which saturates at `u128::MAX`.
+pub fn full_coverage_stats(
+    masks_counts: &HashMap<String, usize>,
+    charset_sizes: &CharsetSizes,
+) -> (usize, u128) {
+    let total_keyspace = masks_counts
+        .keys()
+        .map(|mask| compute_mask_keyspace(mask, charset_sizes).unwrap_or(0))
+        .fold(0u128, u128::saturating_add);
+
+    (masks_counts.len(), total_keyspace)
+}
+
+/// Fewest masks, taken by decreasing count, reaching `target` coverage of `total_words`, along
+/// with the coverage they actually achieve.
+///
+/// Ties are broken by smaller keyspace. All the masks are returned when the target cannot be
+/// reached.
+pub fn masks_for_coverage(
+    masks: &[ComputedMask],
+    total_words: usize,
+    target: f64,
+) -> (Vec<ComputedMask>, f64) {
+    let mut candidates: Vec<&ComputedMask> = masks.iter().collect();
+    candidates.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then(a.size.cmp(&b.size))
+            .then_with(|| a.mask.cmp(&b.mask))
+    });
+
+    let mut seen_masks = HashSet::with_capacity(candidates.len());
+    let mut selected = Vec::new();
+    let mut covered_words = 0usize;
+    for mask in candidates {
+        if total_words == 0 || covered_words as f64 / total_words as f64 >= target {
+            break;
+        }
+        if seen_masks.insert(mask.mask.as_str()) {
+            covered_words = covered_words.saturating_add(mask.count);
+            selected.push(mask.clone());
+        }
+    }
+
+    let coverage = combined_coverage(&selected, total_words);
+    (selected, coverage)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        generate_masks_from_bufreader, parse_reader, sort_masks, CharsetSizes, MaskConfig,
+        ParseOptions, ReadOptions,
+    };
+
+    #[test]
+    fn special_chars_observed() {
+        let config = MaskConfig::default();
+        let wordlist = Cursor::new("pass!\n@dmin!\nw\u{e4}rd#\n12\n".as_bytes());
+        let special_chars = observed_special_chars(wordlist, &config).unwrap();
+
+        assert_eq!(special_chars, BTreeSet::from(['!', '@']));
+    }
+
+    #[test]
+    fn word_length_distribution() {
+        let mut wordlist = Cursor::new("Hello\n\u{e9}t\u{e9}\nabc\n\nFriend");
+        let lengths_counts = length_distribution(&mut wordlist).unwrap();
+
+        assert_eq!(
+            lengths_counts.into_iter().collect::<Vec<_>>(),
+            [(3, 2), (5, 1), (6, 1)]
+        );
+    }
+
+    #[test]
+    fn class_frequencies_per_position() {
+        let mut wordlist = Cursor::new(b"Hello\nhi1\nP\xc3\xa4ss\nA");
+        let positions_counts = position_stats(&mut wordlist, &MaskConfig::default()).unwrap();
+
+        assert_eq!(positions_counts.len(), 5);
+        assert_eq!(positions_counts[0][&'u'], 2);
+        assert_eq!(positions_counts[0][&'l'], 1);
+        assert_eq!(positions_counts[2][&'d'], 1);
+        assert_eq!(positions_counts[2][&'l'], 1);
+        assert_eq!(positions_counts[4].values().sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn class_fractions_per_position() {
+        let mut wordlist = Cursor::new(b"Ab1\nab\nc");
+        let positions_counts = position_stats(&mut wordlist, &MaskConfig::default()).unwrap();
+        let fractions = position_fractions(&positions_counts);
+
+        assert_eq!(fractions.len(), 3);
+        assert_eq!(fractions[0].words, 3);
+        assert!((fractions[0].uppercase - 1.0 / 3.0).abs() < 1e-9);
+        assert!((fractions[0].lowercase - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(fractions[1].words, 2);
+        assert_eq!(fractions[1].lowercase, 1.0);
+        assert_eq!((fractions[2].words, fractions[2].digit), (1, 1.0));
+    }
+
+    #[test]
+    fn analyze_every_mask() {
+        let path = std::env::temp_dir().join("speedy_mask_analyze_every_mask.txt");
+        std::fs::write(&path, "Hello\nWorld\nabc\n12345678901234567890\n").unwrap();
+        let options = ParseOptions {
+            maximum_size: 1,
+            ..ParseOptions::default()
+        };
+        let distribution = analyze_files(&[&path], &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let masks: Vec<&str> = distribution
+            .masks
+            .iter()
+            .map(|mask| mask.mask.as_str())
+            .collect();
+        assert_eq!(masks, ["lll", "ullll"]);
+        assert_eq!(distribution.total_words, 4);
+        assert_eq!(distribution.distinct_masks, 3);
+        assert_eq!(
+            distribution.total_keyspace,
+            17576 + 11881376 + 10u128.pow(20)
+        );
+    }
+
+    #[test]
+    fn combined_coverage_counts_masks_once() {
+        let mut wordlist = Cursor::new(b"Hello\nWorld\nabc\n1234");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+        let ullll = mask_list.iter().find(|mask| mask.mask == "ullll").unwrap();
+
+        let picked = [ullll.clone(), ullll.clone()];
+        assert_eq!(combined_coverage(&picked, mask_counts.total_words), 0.5);
+        assert_eq!(combined_coverage(&mask_list, mask_counts.total_words), 1.0);
+        assert_eq!(combined_coverage(&mask_list, 0), 0.0);
+    }
+
+    #[test]
+    fn full_coverage_ceiling() {
+        let mut wordlist = Cursor::new(b"Hello\nWorld\nabc\n1234");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let charset_sizes = CharsetSizes::default();
+
+        assert_eq!(
+            full_coverage_stats(&mask_counts.masks, &charset_sizes),
+            (3, 11881376 + 17576 + 10000)
+        );
+        let long_masks = HashMap::from([("d".repeat(39), 1), ("s".repeat(30), 1)]);
+        assert_eq!(
+            full_coverage_stats(&long_masks, &charset_sizes).1,
+            u128::MAX
+        );
+    }
+
+    #[test]
+    fn fewest_masks_for_coverage() {
+        let mut wordlist = Cursor::new(b"abc\nabc\nabc\nAbc\nAbc\n123\na1");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+
+        let (selected, coverage) = masks_for_coverage(&mask_list, mask_counts.total_words, 0.7);
+        let selected: Vec<&str> = selected.iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(selected, ["lll", "ull"]);
+        assert!((coverage - 5.0 / 7.0).abs() < 1e-9);
+
+        let (selected, coverage) = masks_for_coverage(&mask_list, mask_counts.total_words, 1.5);
+        assert_eq!(selected.len(), 4);
+        assert_eq!(coverage, 1.0);
+    }
+
+    #[test]
+    fn verified_coverage_matches_counts() {
+        let wordlist = b"Hello\nWorld\nabc\n1234\n\nn\xc3\xa9\nabc";
+        let result = parse_reader(&mut Cursor::new(wordlist), &ParseOptions::default()).unwrap();
+        let selected = &result.masks[..2];
+
+        let config = MaskConfig::default();
+        let read_options = ReadOptions::default();
+        let matched_words =
+            verify_coverage(Cursor::new(wordlist), selected, &config, &read_options).unwrap();
+        assert_eq!(
+            matched_words,
+            selected.iter().map(|mask| mask.count).sum::<usize>()
+        );
+        assert_eq!(
+            verify_coverage(Cursor::new(wordlist), &result.masks, &config, &read_options).unwrap(),
+            result.total_words - result.skipped.words
+        );
+    }
+
+    #[test]
+    fn weighted_wordlist_reread() {
+        let wordlist = b"3\tabc\n2\tHello\nbad line\n1\tn\xc3\xa9";
+        let options = ParseOptions {
+            read: ReadOptions {
+                weight_delimiter: Some('\t'),
+                ..ReadOptions::default()
+            },
+            ..ParseOptions::default()
+        };
+        let result = parse_reader(&mut Cursor::new(wordlist), &options).unwrap();
+        assert_eq!(result.masks.len(), 2);
+
+        let config = MaskConfig::default();
+        let matched_words =
+            verify_coverage(Cursor::new(wordlist), &result.masks, &config, &options.read);
+        assert_eq!(matched_words.unwrap(), 5);
+
+        let selected_masks = HashSet::from(["lll".to_string()]);
+        let words: Vec<String> = uncovered_words(
+            Cursor::new(wordlist),
+            &selected_masks,
+            &config,
+            &options.read,
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(words, ["Hello", "né"]);
+
+        let charsets = position_charsets(
+            Cursor::new(wordlist),
+            &selected_masks,
+            &config,
+            &options.read,
+        );
+        assert_eq!(charsets.unwrap()["lll"][0], BTreeSet::from(['a']));
+    }
+
+    #[test]
+    fn uncovered_words_left_out_of_selection() {
+        let wordlist = b"Hello\nWorld\nabc\n\n1234\nn\xc3\xa9";
+        let selected_masks = HashSet::from(["ullll".to_string()]);
+        let words: Vec<String> = uncovered_words(
+            Cursor::new(wordlist),
+            &selected_masks,
+            &MaskConfig::default(),
+            &ReadOptions::default(),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(words, ["abc", "1234", "né"]);
+    }
+
+    #[test]
+    fn masks_grouped_by_length() {
+        let mut wordlist = Cursor::new(b"Hello\nabc\nabc\n123\nWorld\nab");
+        let mask_counts =
+            generate_masks_from_bufreader(&mut wordlist, &MaskConfig::default()).unwrap();
+        let mask_list = sort_masks(&mask_counts.masks, usize::MAX, &MaskConfig::default());
+
+        let groups = group_by_length(&mask_list);
+        let lengths: Vec<usize> = groups.keys().copied().collect();
+        assert_eq!(lengths, [2, 3, 5]);
+        let three: Vec<&str> = groups[&3].iter().map(|mask| mask.mask.as_str()).collect();
+        assert_eq!(three, ["ddd", "lll"]);
+    }
+}